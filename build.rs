@@ -8,7 +8,7 @@ fn main() {
         Command::new(r"C:\Program Files (x86)\Windows Kits\10\bin\10.0.26100.0\x64\rc.exe")
             .current_dir(&manifest_dir)
             .arg("icon.rc")
-            .spawn()
+            .status()
             .unwrap();
         println!("cargo:rustc-link-arg=icon.res");
     }
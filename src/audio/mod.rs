@@ -1,2 +1,3 @@
 pub mod post_process;
 pub mod read_write;
+pub mod stretch;
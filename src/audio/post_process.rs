@@ -5,7 +5,7 @@ use crate::{
 use anyhow::Result;
 use biquad::{DirectForm2Transposed, Type, Q_BUTTERWORTH_F64};
 
-pub fn peak_compression(signal: &mut Vec<f64>, peak: f64) -> Result<()> {
+pub fn peak_compression(signal: &mut [f64], peak: f64) -> Result<()> {
     // peak compression
     if signal.len() < consts::FFT_SIZE as usize {
         println!("Render too short. Not compressing.");
@@ -35,7 +35,7 @@ pub fn peak_compression(signal: &mut Vec<f64>, peak: f64) -> Result<()> {
     // turn rms to compression envelope
     let env_max = 1. / peak - 1.;
     comp.iter_mut().for_each(|x| {
-        *x = *x / (peak * comp_max);
+        *x /= peak * comp_max;
         *x = if *x >= 1. {
             1. - (1. - peak) * (*x - 1.) / env_max
         } else {
@@ -61,7 +61,7 @@ pub fn peak_compression(signal: &mut Vec<f64>, peak: f64) -> Result<()> {
     Ok(())
 }
 
-pub fn peak_normalization(signal: &mut Vec<f64>, db_norm: f64) {
+pub fn peak_normalization(signal: &mut [f64], db_norm: f64) {
     // normalize
     let norm = (-std::f64::consts::LN_10 * db_norm / 20.).exp();
     let peak: f64 = signal.iter().fold(-1., |acc, x| acc.max(x.abs()));
@@ -75,12 +75,7 @@ pub fn read_audio<P: AsRef<Path>>(path: P) -> Result<Vec<f64>> {
     let mut channels = 1;
     let mut fs = 1;
     let mut packet_buffer = None;
-    loop {
-        let packet = match format.next_packet() {
-            Ok(p) => p,
-            Err(_) => break,
-        };
-
+    while let Ok(packet) = format.next_packet() {
         if packet.track_id() != track_id {
             continue;
         }
@@ -120,7 +115,7 @@ pub fn read_audio<P: AsRef<Path>>(path: P) -> Result<Vec<f64>> {
     }
 }
 
-pub fn write_audio<P: AsRef<Path>>(path: P, audio: &Vec<f64>) -> Result<()> {
+pub fn write_audio<P: AsRef<Path>>(path: P, audio: &[f64]) -> Result<()> {
     // Write audio as 44.1kHz 16-bit WAV
     // UTAU also only works with this specific WAV encoding
     let out_spec = WavSpec {
@@ -131,7 +126,7 @@ pub fn write_audio<P: AsRef<Path>>(path: P, audio: &Vec<f64>) -> Result<()> {
     };
     let mut writer = WavWriter::create(path.as_ref(), out_spec)?;
     let mut scaled_audio: Vec<f64> = audio
-        .into_iter()
+        .iter()
         .map(|x| (x * i16::MAX as f64).clamp(i16::MIN as f64, i16::MAX as f64))
         .collect();
     for s in 0..scaled_audio.len() {
@@ -161,7 +156,7 @@ mod tests {
             "test/paul.wav",
             "test/res.wav",
         ];
-        let test_paths: Vec<&Path> = test_paths.into_iter().map(|x| Path::new(x)).collect();
+        let test_paths: Vec<&Path> = test_paths.into_iter().map(Path::new).collect();
         for path in test_paths {
             println!("Testing {:?}", path.as_os_str());
             let mut out_fname = path.file_name().expect("Failed to get filename").to_owned();
@@ -0,0 +1,137 @@
+use std::ops::Range;
+
+// WSOLA frame size, hop and search tolerance in samples
+const FRAME_SIZE: usize = 1024;
+const HOP_SIZE: usize = FRAME_SIZE / 2;
+const TOLERANCE: usize = 256;
+
+fn sample_or_zero(signal: &[f64], i: isize) -> f64 {
+    if i < 0 || i as usize >= signal.len() {
+        0.
+    } else {
+        signal[i as usize]
+    }
+}
+
+pub fn wsola(signal: &[f64], source_pos: &[f64]) -> Vec<f64> {
+    // Time stretch a signal with WSOLA following a time map. source_pos has the position in the
+    // source signal (in samples) for every output sample, so any stretch curve can be used.
+    // Pitch is kept since frames are copied as is, only their placement changes.
+    let out_length = source_pos.len();
+    let mut out = vec![0.; out_length + FRAME_SIZE];
+    if out_length == 0 {
+        return Vec::new();
+    }
+
+    // hann window, sums to 1 at 50% overlap
+    let window: Vec<f64> = (0..FRAME_SIZE)
+        .map(|i| 0.5 - 0.5 * (std::f64::consts::TAU * i as f64 / FRAME_SIZE as f64).cos())
+        .collect();
+
+    let frames = out_length / HOP_SIZE + 1;
+    let mut prev_start: Option<isize> = None;
+    for k in 0..frames {
+        let out_start = k * HOP_SIZE;
+        let nominal = source_pos[out_start.min(out_length - 1)].round() as isize;
+
+        // look for the segment around the nominal position that best continues the last frame
+        let start = match prev_start {
+            None => nominal,
+            Some(prev) => {
+                let natural = prev + HOP_SIZE as isize;
+                let mut best = nominal;
+                let mut best_corr = f64::MIN;
+                for delta in -(TOLERANCE as isize)..=TOLERANCE as isize {
+                    // normalized cross-correlation, candidates with more energy aren't favored
+                    let candidate = nominal + delta;
+                    let (corr, energy) =
+                        (0..FRAME_SIZE as isize)
+                            .step_by(2)
+                            .fold((0., 0.), |(corr, energy), i| {
+                                let c = sample_or_zero(signal, candidate + i);
+                                (
+                                    corr + c * sample_or_zero(signal, natural + i),
+                                    energy + c * c,
+                                )
+                            });
+                    let corr = corr / energy.sqrt().max(1e-12);
+                    if corr > best_corr {
+                        best_corr = corr;
+                        best = candidate;
+                    }
+                }
+                best
+            }
+        };
+
+        out[out_start..out_start + FRAME_SIZE]
+            .iter_mut()
+            .zip(window.iter())
+            .enumerate()
+            .for_each(|(i, (x, w))| *x += w * sample_or_zero(signal, start + i as isize));
+        prev_start = Some(start);
+    }
+
+    // the first half frame only has one window, use the signal as is there
+    (0..HOP_SIZE.min(out_length)).for_each(|i| {
+        out[i] = sample_or_zero(signal, source_pos[i].round() as isize);
+    });
+
+    out.truncate(out_length);
+    out
+}
+
+pub fn best_lag(reference: &[f64], signal: &[f64], range: Range<usize>, max_lag: usize) -> isize {
+    // Find the lag that best aligns signal to reference within range, signal[i - lag] ~ reference[i]
+    let mut best = 0;
+    let mut best_corr = f64::MIN;
+    for lag in -(max_lag as isize)..=max_lag as isize {
+        let corr: f64 = range
+            .clone()
+            .map(|i| {
+                sample_or_zero(reference, i as isize) * sample_or_zero(signal, i as isize - lag)
+            })
+            .sum();
+        if corr > best_corr {
+            best_corr = corr;
+            best = lag;
+        }
+    }
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{best_lag, wsola};
+
+    #[test]
+    fn test_wsola() {
+        // identity map should give the signal back outside the edges
+        let signal: Vec<f64> = (0..8192).map(|i| (i as f64 * 0.05).sin()).collect();
+        let source_pos: Vec<f64> = (0..8192).map(|i| i as f64).collect();
+        let out = wsola(&signal, &source_pos);
+        assert_eq!(out.len(), 8192);
+        for i in 0..7000 {
+            assert!((out[i] - signal[i]).abs() < 1e-6);
+        }
+
+        // stretching by two should keep the period of the sine
+        let source_pos: Vec<f64> = (0..16384).map(|i| i as f64 / 2.).collect();
+        let out = wsola(&signal, &source_pos);
+        assert_eq!(out.len(), 16384);
+        let period = std::f64::consts::TAU / 0.05;
+        let zero_crossings = out[1024..15360]
+            .windows(2)
+            .filter(|w| w[0] < 0. && w[1] >= 0.)
+            .count() as f64;
+        let expected = (15360 - 1024) as f64 / period;
+        assert!((zero_crossings - expected).abs() <= 2.);
+    }
+
+    #[test]
+    fn test_best_lag() {
+        let reference: Vec<f64> = (0..512).map(|i| (i as f64 * 0.1).sin()).collect();
+        let signal: Vec<f64> = (0..512).map(|i| ((i + 7) as f64 * 0.1).sin()).collect();
+        assert_eq!(best_lag(&reference, &signal, 100..400, 20), 7);
+    }
+}
@@ -8,5 +8,44 @@ pub const FRAME_PERIOD: f64 = 5.;
 pub const FFT_SIZE: i32 = 2048;
 pub const D4C_THRESHOLD: f64 = 0.25;
 pub const MGC_DIMS: i32 = 64;
+// Stable region detection. The pitch deviation window is in frames on each side, unvoiced frames
+// count as this many semitones and the stable span is relative to the stretch region, in frames
+// at the least
+pub const INSTABILITY_WINDOW: usize = 5;
+pub const UNVOICED_INSTABILITY: f64 = 12.;
+pub const STABLE_REGION_RATIO: f64 = 0.4;
+pub const STABLE_REGION_MIN: f64 = 20.;
+// Variation for stretched notes, frames between random drift points and independent drift curves
+// across the spectrum
+pub const VARIATION_PERIOD: usize = 40;
+pub const VARIATION_BANDS: usize = 8;
+// Consonant segmentation for velocity modes. Closures are this many dB below the loudest
+// consonant frame, bursts start with an energy rise over two frames and take the frames after it.
+// Consonant parts never get shorter than this relative to the sample
+pub const CLOSURE_DB: f64 = 30.;
+pub const BURST_RISE_DB: f64 = 12.;
+pub const BURST_SPREAD: usize = 2;
+pub const MIN_CONSONANT_SPEED: f64 = 0.1;
+// Tension curve in Hz. The pivot keeps its level, lower frequencies are tilted like the floor
+pub const TENSION_PIVOT: f64 = 1000.;
+pub const TENSION_FLOOR: f64 = 100.;
+// How close a note has to be to the sample to skip resynthesis, in semitones and WORLD frames,
+// and the fade at the edges of the trimmed sample in seconds
+pub const BYPASS_PITCH_TOLERANCE: f64 = 0.05;
+pub const BYPASS_TIME_TOLERANCE: f64 = 1.;
+pub const BYPASS_FADE: f64 = 0.005;
 // If you want to change the extension for some reason
-pub const FEATURE_EXT: &'static str = "sc";
+pub const FEATURE_EXT: &str = "sc";
+// Preset file next to the resampler executable
+pub const PRESET_FILE: &str = "straycat-rs.presets";
+// Scala tuning files next to the resampler executable
+pub const SCALE_FILE: &str = "straycat-rs.scl";
+pub const KEYMAP_FILE: &str = "straycat-rs.kbm";
+// Random seeds, mixed into the render seed so each stage gets its own noise
+pub const GROWL_SEED: u64 = 0x4752_4f57;
+pub const NOISE_SEED: u64 = 0x4e4f_4953;
+pub const VARIATION_SEED: u64 = 0x5743_4154;
+pub const VIBRATO_SEED: u64 = 0x5649_4252;
+// Vibrato drift at full strength, relative to the rate and depth
+pub const VIBRATO_RATE_DRIFT: f64 = 0.15;
+pub const VIBRATO_DEPTH_DRIFT: f64 = 0.25;
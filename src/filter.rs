@@ -1,11 +1,10 @@
 use anyhow::{anyhow, Result};
 use biquad::{Biquad, Coefficients, ToHertz, Type};
 
-pub fn forward_backward_filter<F: Biquad<f64>>(
-    signal: &mut Vec<f64>,
-    filter: &mut F,
-    repeats: usize,
-) {
+// how many samples a time-varying filter keeps its coefficients for
+pub const SVF_BLOCK_SIZE: usize = 16;
+
+pub fn forward_backward_filter<F: Biquad<f64>>(signal: &mut [f64], filter: &mut F, repeats: usize) {
     // do forward-backward filtering on any biquad filter
     for _ in 0..repeats {
         signal.iter_mut().for_each(|x| *x = filter.run(*x)); // forward pass
@@ -27,3 +26,158 @@ pub fn make_coefficients(
         Err(_) => Err(anyhow!("Can't make filter coefficients.")),
     }
 }
+
+pub struct StateVariableFilter {
+    // Trapezoidal state variable high-pass filter. Stays stable when the cutoff moves every sample
+    // Based on https://cytomic.com/files/dsp/SvfLinearTrapOptimised2.pdf
+    fs: f64,
+    k: f64,
+    a1: f64,
+    a2: f64,
+    a3: f64,
+    ic1eq: f64,
+    ic2eq: f64,
+}
+
+impl StateVariableFilter {
+    pub fn new(fs: f64, f0: f64, q_value: f64) -> Result<Self> {
+        if q_value <= 0. {
+            return Err(anyhow!("Can't make filter with non-positive Q."));
+        }
+        let mut svf = Self {
+            fs,
+            k: 1. / q_value,
+            a1: 0.,
+            a2: 0.,
+            a3: 0.,
+            ic1eq: 0.,
+            ic2eq: 0.,
+        };
+        svf.set_cutoff(f0);
+        Ok(svf)
+    }
+
+    pub fn set_cutoff(&mut self, f0: f64) {
+        // cheap enough to call per sample, keeps the filter state intact
+        let f0 = f0.clamp(1e-3, 0.499 * self.fs);
+        let g = (std::f64::consts::PI * f0 / self.fs).tan();
+        self.a1 = 1. / (1. + g * (g + self.k));
+        self.a2 = g * self.a1;
+        self.a3 = g * self.a2;
+    }
+
+    pub fn run(&mut self, x: f64) -> f64 {
+        let v3 = x - self.ic2eq;
+        let v1 = self.a1 * self.ic1eq + self.a2 * v3;
+        let v2 = self.ic2eq + self.a2 * self.ic1eq + self.a3 * v3;
+        self.ic1eq = 2. * v1 - self.ic1eq;
+        self.ic2eq = 2. * v2 - self.ic2eq;
+
+        x - self.k * v1 - v2 // high-pass output
+    }
+
+    pub fn reset_state(&mut self) {
+        self.ic1eq = 0.;
+        self.ic2eq = 0.;
+    }
+}
+
+pub fn forward_backward_filter_varying(
+    signal: &mut [f64],
+    filter: &mut StateVariableFilter,
+    cutoff: &[f64],
+    repeats: usize,
+) {
+    // forward-backward filtering with a cutoff curve that has one value per sample
+    // coefficients are updated per block since the cutoff usually moves slowly
+    assert_eq!(
+        cutoff.len(),
+        signal.len(),
+        "Cutoff curve needs one value per sample."
+    );
+    for _ in 0..repeats {
+        signal
+            .chunks_mut(SVF_BLOCK_SIZE)
+            .zip(cutoff.chunks(SVF_BLOCK_SIZE))
+            .for_each(|(block, fc)| {
+                filter.set_cutoff(fc[0]);
+                block.iter_mut().for_each(|x| *x = filter.run(*x));
+            }); // forward pass
+        filter.reset_state(); // filter reset
+        signal
+            .rchunks_mut(SVF_BLOCK_SIZE)
+            .zip(cutoff.rchunks(SVF_BLOCK_SIZE))
+            .for_each(|(block, fc)| {
+                filter.set_cutoff(fc[fc.len() - 1]);
+                block.iter_mut().rev().for_each(|x| *x = filter.run(*x));
+            }); // backward pass
+        filter.reset_state(); // filter reset
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{forward_backward_filter_varying, StateVariableFilter};
+    use crate::consts;
+    use biquad::Q_BUTTERWORTH_F64;
+
+    fn sine(freq: f64, n: usize) -> Vec<f64> {
+        let fs = consts::SAMPLE_RATE as f64;
+        (0..n)
+            .map(|i| (2. * std::f64::consts::PI * freq * i as f64 / fs).sin())
+            .collect()
+    }
+
+    fn rms(x: &[f64]) -> f64 {
+        (x.iter().map(|x| x * x).sum::<f64>() / x.len() as f64).sqrt()
+    }
+
+    #[test]
+    fn test_svf_highpass() {
+        let fs = consts::SAMPLE_RATE as f64;
+        let n = consts::SAMPLE_RATE as usize;
+        let mut svf =
+            StateVariableFilter::new(fs, 200., Q_BUTTERWORTH_F64).expect("Cannot make filter");
+
+        let mut low = sine(50., n);
+        low.iter_mut().for_each(|x| *x = svf.run(*x));
+        svf.reset_state();
+        let mut high = sine(2000., n);
+        high.iter_mut().for_each(|x| *x = svf.run(*x));
+
+        // second order butterworth, two octaves below cutoff is about -24 dB
+        assert!(rms(&low[n / 2..]) < 0.1);
+        assert!((rms(&high[n / 2..]) - 0.5f64.sqrt()).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_svf_varying_cutoff() {
+        let fs = consts::SAMPLE_RATE as f64;
+        let n = consts::SAMPLE_RATE as usize;
+        let mut svf =
+            StateVariableFilter::new(fs, 100., Q_BUTTERWORTH_F64).expect("Cannot make filter");
+
+        // cutoff sweeps from below to above the sine, so the second half should be attenuated
+        let cutoff: Vec<f64> = (0..n)
+            .map(|i| if i < n / 2 { 100. } else { 1600. })
+            .collect();
+        let mut signal = sine(400., n);
+        forward_backward_filter_varying(&mut signal, &mut svf, &cutoff, 1);
+
+        assert!(signal.iter().all(|x| x.is_finite()));
+        let first = rms(&signal[n / 8..3 * n / 8]);
+        let second = rms(&signal[5 * n / 8..7 * n / 8]);
+        assert!(first > 0.6);
+        assert!(second < 0.1);
+    }
+
+    #[test]
+    #[should_panic(expected = "one value per sample")]
+    fn test_svf_cutoff_length() {
+        // a short cutoff curve would leave the end of the signal unfiltered
+        let mut svf = StateVariableFilter::new(consts::SAMPLE_RATE as f64, 100., Q_BUTTERWORTH_F64)
+            .expect("Cannot make filter");
+        let mut signal = sine(400., 1000);
+        forward_backward_filter_varying(&mut signal, &mut svf, &[100.; 999], 1);
+    }
+}
@@ -0,0 +1,110 @@
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use crate::interpolator::interp::{make_interpolator_with_knots, InterpolatorType};
+
+// A curve is a list of [time in ms from the start of the render, value] points
+pub type Curve = Vec<[f64; 2]>;
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ExpressionCurves {
+    // values use the same units as the flags they replace
+    pub gender: Option<Curve>,
+    pub breathiness: Option<Curve>,
+    pub growl: Option<Curve>,
+    pub tension: Option<Curve>,
+    pub volume: Option<Curve>,
+}
+
+pub fn to_curve_path<P: AsRef<Path>>(path: P) -> PathBuf {
+    // Sidecar file is the output file with a json extension
+    path.as_ref().with_extension("json")
+}
+
+pub fn read_curves<P: AsRef<Path>>(path: P) -> Result<ExpressionCurves> {
+    // Read and check expression curves from a sidecar file
+    let mut json = String::new();
+    let mut f = File::open(path)?;
+    f.read_to_string(&mut json)?;
+
+    let curves: ExpressionCurves = serde_json::from_str(&json)?;
+    for curve in [
+        &curves.gender,
+        &curves.breathiness,
+        &curves.growl,
+        &curves.tension,
+        &curves.volume,
+    ]
+    .into_iter()
+    .flatten()
+    {
+        if curve.is_empty() {
+            return Err(anyhow!("Expression curve has no points."));
+        }
+        if curve.windows(2).any(|w| w[1][0] < w[0][0]) {
+            return Err(anyhow!("Expression curve times must be in order."));
+        }
+    }
+    Ok(curves)
+}
+
+impl ExpressionCurves {
+    pub fn is_empty(&self) -> bool {
+        self.gender.is_none()
+            && self.breathiness.is_none()
+            && self.growl.is_none()
+            && self.tension.is_none()
+            && self.volume.is_none()
+    }
+}
+
+pub fn render_curve(curve: &Option<Curve>, default: f64, t: &[f64]) -> Result<Vec<f64>> {
+    // Value of a curve at every timestamp in seconds, or the flag value if there is no curve.
+    // Linear between points, holds the first and last values
+    let Some(curve) = curve else {
+        return Ok(vec![default; t.len()]);
+    };
+    let (times, values): (Vec<f64>, Vec<f64>) = curve.iter().map(|[t, v]| (t / 1000., *v)).unzip();
+    let interp = make_interpolator_with_knots(&times, &values, InterpolatorType::Linear)?;
+    Ok(interp.sample_with_vec(t))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{render_curve, ExpressionCurves};
+
+    #[test]
+    fn test_curves() {
+        let json = r#"{"gender": [[0, 0], [100, 20], [200, -20]], "volume": [[50, 80]]}"#;
+        let curves: ExpressionCurves = serde_json::from_str(json).expect("Cannot parse curves");
+        assert!(!curves.is_empty());
+        assert!(curves.breathiness.is_none());
+
+        let t = [-0.01, 0.05, 0.15, 1.];
+        let gender = render_curve(&curves.gender, 0., &t).unwrap();
+        for (g, expected) in gender.iter().zip([0., 10., 0., -20.]) {
+            assert!((g - expected).abs() < 1e-9, "{g}");
+        }
+        // points at the same time jump
+        let step = Some(vec![[0., 0.], [100., 0.], [100., 50.], [200., 50.]]);
+        assert_eq!(
+            render_curve(&step, 0., &[0.05, 0.1, 0.15]).unwrap(),
+            vec![0., 50., 50.]
+        );
+
+        // missing curves use the flag value
+        let t = [0., 0.05, 0.1];
+        assert_eq!(
+            render_curve(&curves.volume, 100., &t).unwrap(),
+            vec![80.; 3]
+        );
+        assert_eq!(render_curve(&curves.growl, 30., &t).unwrap(), vec![30.; 3]);
+
+        // typos are errors instead of silently ignored
+        assert!(serde_json::from_str::<ExpressionCurves>(r#"{"gendr": []}"#).is_err());
+    }
+}
@@ -1 +1,4 @@
+pub mod curves;
 pub mod parser;
+pub mod presets;
+pub mod registry;
@@ -1,9 +1,13 @@
-use anyhow::Result;
-use std::{mem::discriminant, str::FromStr};
+use anyhow::{anyhow, Result};
+use std::{fmt, str::FromStr};
 
-use crate::consts;
+use crate::flags::presets::Presets;
+use crate::flags::registry::{find_flag, FlagSpec, FLAGS};
+use crate::interpolator::interp::InterpolatorType;
+use crate::quantize::Scale;
+use crate::timing::{ConsonantMode, StretchMode};
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct Flags {
     pub generate_features: Option<f64>,
     pub fry_enable: f64,
@@ -22,192 +26,449 @@ pub struct Flags {
     pub pitch_offset: f64,
     pub aperiodic_mix: f64,
     pub growl: f64,
+    pub harmonic_noise: bool,
+    pub hybrid_consonant: f64,
+    pub stretch_mode: StretchMode,
+    pub variation: f64,
+    pub consonant_mode: ConsonantMode,
+    pub seed: Option<u64>,
+    pub quantize: f64,
+    pub quantize_speed: f64,
+    pub quantize_scale: Scale,
+    pub quantize_root: f64,
+    pub vibrato_rate: f64,
+    pub vibrato_depth: f64,
+    pub vibrato_delay: f64,
+    pub vibrato_fade: f64,
+    pub vibrato_drift: f64,
+    pub tremolo_follow: bool,
+    pub tremolo_low: f64,
+    pub tremolo_high: f64,
+    pub tremolo_rate: f64,
+    pub tremolo_depth: f64,
+    pub tremolo_phase: f64,
+    pub tuning_reference: Option<f64>,
+    pub tuning_edo: Option<f64>,
+    pub pitch_interpolation: InterpolatorType,
+    // set when the flag string changes a flag that changes the sound of the sample
+    pub changes_timbre: bool,
 }
 
-enum FlagToken {
-    GenerateFeatures,
-    FryEnable,
-    FryOffset,
-    FryTransition,
-    FryVolume,
-    FryPitch,
-    DevoiceEnable,
-    DevoiceOffset,
-    DevoiceTransition,
-    Gender,
-    Breathiness,
-    PeakCompression,
-    PeakNormalization,
-    Tremolo,
-    PitchOffset,
-    AperiodicMix,
-    Growl,
+#[derive(Debug, Clone, Copy)]
+enum Token {
+    Flag(&'static FlagSpec),
     Number(f64),
 }
 
 impl Flags {
     pub fn new() -> Self {
-        Self {
-            generate_features: None,
-            fry_enable: 0.,
-            fry_offset: 0.,
-            fry_transition: 75.,
-            fry_volume: 10.,
-            fry_pitch: consts::F0_FLOOR,
-            devoice_enable: 0.,
-            devoice_offset: 0.,
-            devoice_transition: 75.,
-            gender: 0.,
-            breathiness: 50.,
-            peak_compression: 86.,
-            peak_normalization: 4.,
-            tremolo: 0.,
-            pitch_offset: 0.,
-            aperiodic_mix: 0.,
-            growl: 0.,
+        // defaults come from the flag table
+        let mut flags = Self::default();
+        FLAGS
+            .iter()
+            .for_each(|spec| spec.default.iter().for_each(|v| (spec.set)(&mut flags, *v)));
+        flags
+    }
+
+    fn set(&mut self, spec: &FlagSpec, value: f64) {
+        // set a flag from the flag string, keeping track of flags that change the sound
+        (spec.set)(self, value);
+        if spec.changes_timbre && spec.default != Some(value) {
+            self.changes_timbre = true;
         }
     }
+
+    pub fn needs_resynthesis(&self) -> bool {
+        // flags that change the sound of the sample itself, marked in the flag table
+        self.changes_timbre
+    }
 }
 
-impl FromStr for Flags {
-    type Err = anyhow::Error;
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    // the flag string was understood but something was adjusted
+    Warning,
+    // the parser had to guess or skip something
+    Error,
+}
 
-    fn from_str(s: &str) -> Result<Self> {
-        let mut flags = Self::new();
-        let mut flag_tokens = Vec::new();
-        let length = s.len();
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    // character position in the flag string
+    pub position: usize,
+    pub message: String,
+}
 
-        // tokenize flags
-        let mut i = 0;
-        while i < length {
-            // current character... as a &str ik
-            let mut curr = &s[i..i + 1];
-            // two characters
-            let two_chars = if i < s.len() - 1 {
-                Some(&s[i..i + 2])
-            } else {
-                None
-            };
-
-            // check single character flags
-            match curr {
-                "g" => flag_tokens.push(FlagToken::Gender),
-                "B" => flag_tokens.push(FlagToken::Breathiness),
-                "P" => flag_tokens.push(FlagToken::PeakCompression),
-                "p" => flag_tokens.push(FlagToken::PeakNormalization),
-                "A" => flag_tokens.push(FlagToken::Tremolo),
-                "t" => flag_tokens.push(FlagToken::PitchOffset),
-                "S" => flag_tokens.push(FlagToken::AperiodicMix),
-                "G" => flag_tokens.push(FlagToken::GenerateFeatures),
-                _ => (),
-            }
+impl Diagnostic {
+    pub fn warning(position: usize, message: String) -> Self {
+        Self {
+            severity: Severity::Warning,
+            position,
+            message,
+        }
+    }
 
-            // check two character flags
-            if let Some(flag) = two_chars {
-                let mut matched = true;
-                match flag {
-                    "fe" => flag_tokens.push(FlagToken::FryEnable),
-                    "fo" => flag_tokens.push(FlagToken::FryOffset),
-                    "fl" => flag_tokens.push(FlagToken::FryTransition),
-                    "fv" => flag_tokens.push(FlagToken::FryVolume),
-                    "fp" => flag_tokens.push(FlagToken::FryPitch),
-                    "ve" => flag_tokens.push(FlagToken::DevoiceEnable),
-                    "vo" => flag_tokens.push(FlagToken::DevoiceOffset),
-                    "vl" => flag_tokens.push(FlagToken::DevoiceTransition),
-                    "gw" => flag_tokens.push(FlagToken::Growl),
-                    _ => matched = false,
-                }
-                if matched {
-                    i += 1; // increment index if matched
-                }
-            }
+    pub fn error(position: usize, message: String) -> Self {
+        Self {
+            severity: Severity::Error,
+            position,
+            message,
+        }
+    }
+}
 
-            // parse numbers
-            if curr.parse::<i32>().is_ok() || curr == "+" || curr == "-" {
-                let st = i;
-                while i + 1 < length && s[i + 1..i + 2].parse::<i32>().is_ok() {
-                    i += 1;
-                    curr = &s[st..i + 1];
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at {}", self.message, self.position)
+    }
+}
+
+fn scan_number(chars: &[char]) -> usize {
+    // length of the number at the start of chars. takes a sign, decimals and an exponent with an
+    // uppercase E, since lowercase e is a flag
+    let digits = |from: usize| {
+        chars
+            .get(from..)
+            .map_or(0, |c| c.iter().take_while(|c| c.is_ascii_digit()).count())
+    };
+    let mut length = matches!(chars.first(), Some('+' | '-')) as usize;
+    length += digits(length);
+    if chars.get(length) == Some(&'.') && digits(length + 1) > 0 {
+        length += 1 + digits(length + 1);
+    }
+    if length > 0 && chars.get(length) == Some(&'E') {
+        let sign = matches!(chars.get(length + 1), Some('+' | '-')) as usize;
+        let exponent = digits(length + 1 + sign);
+        if exponent > 0 {
+            length += 1 + sign + exponent;
+        }
+    }
+    length
+}
+
+fn lex(s: &str) -> (Vec<(usize, Token)>, Vec<Diagnostic>) {
+    // Split a flag string into flags and numbers with their positions
+    let chars: Vec<char> = s.chars().collect();
+    let mut tokens = Vec::new();
+    let mut diagnostics = Vec::new();
+
+    let mut i = 0;
+    while i < chars.len() {
+        let curr = chars[i];
+        if let Some(spec) = find_flag(&chars[i..]) {
+            tokens.push((i, Token::Flag(spec)));
+            i += spec.flag.chars().count();
+        } else if curr == '/' || curr.is_whitespace() {
+            // separators some editors put between flags
+            i += 1;
+        } else if curr.is_ascii_digit() || matches!(curr, '+' | '-' | '.') {
+            let length = scan_number(&chars[i..]);
+            let text: String = chars[i..i + length.max(1)].iter().collect();
+            match text.parse() {
+                Ok(value) => tokens.push((i, Token::Number(value))),
+                Err(_) => {
+                    diagnostics.push(Diagnostic::error(i, format!("malformed number `{text}`")))
                 }
-                flag_tokens.push(FlagToken::Number(curr.parse()?));
             }
-
-            // increment
+            i += length.max(1);
+        } else {
+            diagnostics.push(Diagnostic::error(i, format!("unknown flag `{curr}`")));
             i += 1;
         }
+    }
 
-        i = 0; // recycle index
-        let length = flag_tokens.len();
-        // parse flags
-        while i < length {
-            let curr = &flag_tokens[i];
-            // if it's not a numerical token
-            if discriminant(curr) != discriminant(&FlagToken::Number(0.)) {
-                if i < length - 1 {
-                    // get next token
-                    let next = &flag_tokens[i + 1];
-                    if let FlagToken::Number(value) = next {
-                        match curr {
-                            FlagToken::GenerateFeatures => {
-                                flags.generate_features = Some(value.clamp(0., 100.))
-                            }
-                            FlagToken::FryEnable => flags.fry_enable = *value,
-                            FlagToken::FryOffset => flags.fry_offset = *value,
-                            FlagToken::FryTransition => flags.fry_transition = value.max(1.),
-                            FlagToken::FryVolume => flags.fry_volume = value.clamp(0., 100.),
-                            FlagToken::FryPitch => flags.fry_pitch = value.max(0.),
-                            FlagToken::DevoiceEnable => flags.devoice_enable = *value,
-                            FlagToken::DevoiceOffset => flags.devoice_offset = *value,
-                            FlagToken::DevoiceTransition => {
-                                flags.devoice_transition = value.max(1.)
-                            }
-                            FlagToken::Gender => flags.gender = *value,
-                            FlagToken::Breathiness => flags.breathiness = value.clamp(0., 100.),
-                            FlagToken::PeakCompression => {
-                                flags.peak_compression = value.clamp(0., 100.)
-                            }
-                            FlagToken::PeakNormalization => flags.peak_normalization = *value,
-                            FlagToken::Tremolo => flags.tremolo = *value,
-                            FlagToken::PitchOffset => flags.pitch_offset = *value,
-                            FlagToken::AperiodicMix => flags.aperiodic_mix = value.clamp(0., 100.),
-                            FlagToken::Growl => flags.growl = value.clamp(0., 100.),
-                            _ => (),
-                        }
-                        i += 1;
-                    } else {
-                        match curr {
-                            FlagToken::GenerateFeatures => {
-                                flags.generate_features = Some(consts::D4C_THRESHOLD * 100.)
-                            }
-                            _ => (),
-                        }
+    (tokens, diagnostics)
+}
+
+impl Flags {
+    pub fn parse_with_diagnostics(s: &str) -> (Self, Vec<Diagnostic>) {
+        // Parse a flag string, reporting anything that was unknown, malformed or adjusted
+        let mut flags = Self::new();
+        let (tokens, mut diagnostics) = lex(s);
+
+        let mut i = 0;
+        while i < tokens.len() {
+            let (position, curr) = tokens[i];
+            let next = tokens.get(i + 1).map(|(_, token)| *token);
+            match (curr, next) {
+                (Token::Number(value), _) => diagnostics.push(Diagnostic::error(
+                    position,
+                    format!("value `{value}` without a flag"),
+                )),
+                (Token::Flag(spec), Some(Token::Number(value))) => {
+                    let (min, max) = spec.range;
+                    let clamped = value.clamp(min, max);
+                    if clamped != value {
+                        diagnostics.push(Diagnostic::warning(
+                            position,
+                            format!(
+                                "value {value} of flag `{}` out of range, clamped to {clamped}",
+                                spec.flag
+                            ),
+                        ));
                     }
-                } else {
-                    // Last token, only check possible option flag
-                    match curr {
-                        FlagToken::GenerateFeatures => {
-                            flags.generate_features = Some(consts::D4C_THRESHOLD * 100.)
-                        }
-                        _ => (),
+                    let rounded = if spec.integer {
+                        clamped.round()
+                    } else {
+                        clamped
+                    };
+                    if rounded != clamped {
+                        diagnostics.push(Diagnostic::warning(
+                            position,
+                            format!(
+                                "value {clamped} of flag `{}` is not a whole number, rounded to {rounded}",
+                                spec.flag
+                            ),
+                        ));
                     }
+                    flags.set(spec, rounded);
+                    i += 1;
                 }
+                // no number follows, only option flags work like this
+                (Token::Flag(spec), _) => match spec.option_value {
+                    Some(value) => flags.set(spec, value),
+                    None => diagnostics.push(Diagnostic::error(
+                        position,
+                        format!("missing value for flag `{}`", spec.flag),
+                    )),
+                },
             }
-            // increment
             i += 1;
         }
 
+        diagnostics.sort_by_key(|d| d.position);
+        (flags, diagnostics)
+    }
+
+    pub fn parse_with_presets(s: &str, presets: &Presets) -> (Self, Vec<Diagnostic>) {
+        // Expand presets, then parse the full flag string. Problems are reported where they are
+        // in s, problems inside a preset at the preset
+        let (expanded, positions, mut diagnostics) = presets.expand(s);
+        if s.contains('@') {
+            println!("Expanded flags: {expanded}");
+        }
+        let (flags, flag_diagnostics) = Self::parse_with_diagnostics(&expanded);
+        diagnostics.extend(flag_diagnostics.into_iter().map(|mut d| {
+            d.position = positions
+                .get(d.position)
+                .copied()
+                .unwrap_or(s.chars().count());
+            d
+        }));
+        diagnostics.sort_by_key(|d| d.position);
+        (flags, diagnostics)
+    }
+
+    pub fn parse_lenient(s: &str, presets: &Presets) -> Self {
+        // Parse a flag string, problems are reported and skipped
+        let (flags, diagnostics) = Self::parse_with_presets(s, presets);
+        diagnostics.iter().for_each(|d| match d.severity {
+            Severity::Warning => println!("Flag warning: {d}."),
+            Severity::Error => println!("Flag error: {d}. Ignoring."),
+        });
+        flags
+    }
+
+    pub fn parse_strict(s: &str, presets: &Presets) -> Result<Self> {
+        // Parse a flag string, failing on anything the parser would have to guess about
+        let (flags, diagnostics) = Self::parse_with_presets(s, presets);
+        let errors: Vec<String> = diagnostics
+            .iter()
+            .filter(|d| d.severity == Severity::Error)
+            .map(|d| d.to_string())
+            .collect();
+        if !errors.is_empty() {
+            return Err(anyhow!("Invalid flags: {}.", errors.join(", ")));
+        }
+        diagnostics
+            .iter()
+            .for_each(|d| println!("Flag warning: {d}."));
         Ok(flags)
     }
 }
 
+impl FromStr for Flags {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        // lenient parsing without presets
+        Ok(Self::parse_lenient(s, &Presets::default()))
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::Flags;
+    use super::{scan_number, Flags, Severity};
+    use crate::flags::presets::Presets;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
 
     #[test]
     fn test_flags() {
         let flag = "f/e1000t100";
         let flags: Flags = flag.replace("/", "").parse().expect("Cannot parse flags");
         println!("{:#?}", flags);
+
+        // option flags without a value
+        let flags: Flags = "eB60".parse().expect("Cannot parse flags");
+        assert_eq!(flags.stretch_mode, crate::timing::StretchMode::Loop);
+        assert_eq!(flags.breathiness, 60.);
+
+        // only flags that change the sound away from their default need resynthesis
+        for (flag_string, resynthesis) in [
+            ("", false),
+            ("B50t30e", false),
+            ("g0fp100", false),
+            ("H", true),
+            ("g5", true),
+            ("B60", true),
+        ] {
+            let flags: Flags = flag_string.parse().expect("Cannot parse flags");
+            assert_eq!(flags.needs_resynthesis(), resynthesis, "{flag_string}");
+        }
+    }
+
+    fn scan(s: &str) -> usize {
+        scan_number(&s.chars().collect::<Vec<char>>())
+    }
+
+    #[test]
+    fn test_numbers() {
+        assert_eq!(scan("12.5g"), 4);
+        assert_eq!(scan("-2.5"), 4);
+        assert_eq!(scan(".5"), 2);
+        assert_eq!(scan("5.g"), 1);
+        assert_eq!(scan("1E-3B"), 4);
+        assert_eq!(scan("2e1"), 1);
+        assert_eq!(scan("3E"), 1);
+        assert_eq!(scan("g1"), 0);
+
+        let flags: Flags = "g-2.5t12.5B1.5E1e1".parse().expect("Cannot parse flags");
+        assert_eq!(flags.gender, -2.5);
+        assert_eq!(flags.pitch_offset, 12.5);
+        assert_eq!(flags.breathiness, 15.);
+        assert_eq!(flags.stretch_mode, crate::timing::StretchMode::Loop);
+
+        // integer flag strings still parse the same
+        let flags: Flags = "g-5B60fe100e2".parse().expect("Cannot parse flags");
+        assert_eq!(flags.gender, -5.);
+        assert_eq!(flags.breathiness, 60.);
+        assert_eq!(flags.fry_enable, 100.);
+        assert_eq!(flags.stretch_mode, crate::timing::StretchMode::PingPong);
+    }
+
+    #[test]
+    fn test_flag_round_trip() {
+        // write many random flag combinations and read them back
+        let mut rng = StdRng::seed_from_u64(37);
+        for _ in 0..2000 {
+            let mut values = [0.; 6];
+            let mut flag_string = String::new();
+            for (i, name) in ["g", "t", "fo", "vo", "A", "P"].iter().enumerate() {
+                let value: f64 = match rng.gen_range(0..3) {
+                    0 => rng.gen_range(-100..=100) as f64,
+                    1 => rng.gen_range(-10000..=10000) as f64 / 100.,
+                    _ => rng.gen_range(-1000..=1000) as f64 / 8.,
+                };
+                values[i] = value;
+                let text = match rng.gen_range(0..3) {
+                    0 => format!("{value}"),
+                    1 => format!("{value:E}"),
+                    _ if value > 0. => format!("+{value}"),
+                    _ => format!("{value}"),
+                };
+                flag_string.push_str(name);
+                flag_string.push_str(&text);
+            }
+            let flags: Flags = flag_string
+                .parse()
+                .unwrap_or_else(|_| panic!("Cannot parse {flag_string}"));
+            let parsed = [
+                flags.gender,
+                flags.pitch_offset,
+                flags.fry_offset,
+                flags.devoice_offset,
+                flags.tremolo,
+                flags.peak_compression,
+            ];
+            assert_eq!(
+                parsed,
+                [
+                    values[0],
+                    values[1],
+                    values[2],
+                    values[3],
+                    values[4],
+                    values[5].clamp(0., 100.)
+                ],
+                "{flag_string}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        let (flags, diagnostics) = Flags::parse_with_diagnostics("g5Q3B150t");
+        assert_eq!(flags.gender, 5.);
+        assert_eq!(flags.breathiness, 100.);
+        let messages: Vec<String> = diagnostics.iter().map(|d| d.to_string()).collect();
+        assert_eq!(
+            messages,
+            [
+                "unknown flag `Q` at 2",
+                "value `3` without a flag at 3",
+                "value 150 of flag `B` out of range, clamped to 100 at 4",
+                "missing value for flag `t` at 8",
+            ]
+        );
+        assert_eq!(diagnostics[2].severity, Severity::Warning);
+
+        // flags that only take whole numbers say when they round
+        let (flags, diagnostics) = Flags::parse_with_diagnostics("Te19.5R3");
+        assert_eq!(flags.tuning_edo, Some(20.));
+        assert_eq!(flags.seed, Some(3));
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].to_string(),
+            "value 19.5 of flag `Te` is not a whole number, rounded to 20 at 0"
+        );
+        assert_eq!(diagnostics[0].severity, Severity::Warning);
+
+        // non-ASCII input is reported instead of panicking
+        let (flags, diagnostics) = Flags::parse_with_diagnostics("gé5/ーB60");
+        assert_eq!(flags.breathiness, 60.);
+        assert_eq!(flags.gender, 5.);
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[0].to_string(), "unknown flag `é` at 1");
+        assert_eq!(diagnostics[1].to_string(), "unknown flag `ー` at 4");
+
+        // strict mode fails on errors but not on warnings
+        let presets = Presets::default();
+        assert!(Flags::parse_strict("g5Q3", &presets).is_err());
+        assert!(Flags::parse_strict("-", &presets).is_err());
+        assert!(Flags::parse_strict("B150H", &presets).is_ok());
+        assert!(Flags::parse_strict("g-5/B60fe100e2Y", &presets).is_err());
+        assert!(Flags::parse_strict("g-5/B60fe100e2", &presets).is_ok());
+
+        // presets only come from what the caller passes in
+        let presets: Presets = "rock = g5B60".parse().unwrap();
+        let flags = Flags::parse_strict("@rockg3", &presets).expect("Cannot parse flags");
+        assert_eq!((flags.gender, flags.breathiness), (3., 60.));
+        assert!(Flags::parse_strict("@rock", &Presets::default()).is_err());
+
+        // positions point into what was typed, not the expanded flags
+        let (_, diagnostics) = Flags::parse_with_presets("@rockQB150", &presets);
+        let messages: Vec<String> = diagnostics.iter().map(|d| d.to_string()).collect();
+        assert_eq!(
+            messages,
+            [
+                "unknown flag `Q` at 5",
+                "value 150 of flag `B` out of range, clamped to 100 at 6",
+            ]
+        );
+        let presets: Presets = "bad = g5Q".parse().unwrap();
+        let (_, diagnostics) = Flags::parse_with_presets("B60@bad", &presets);
+        assert_eq!(diagnostics[0].to_string(), "unknown flag `Q` at 3");
     }
 }
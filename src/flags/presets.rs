@@ -0,0 +1,172 @@
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::flags::parser::Diagnostic;
+use crate::{consts, util};
+
+// presets can use other presets, this stops loops
+const MAX_PRESET_DEPTH: usize = 16;
+
+// characters of a flag string with their position in the string the user wrote
+type Located = Vec<(char, usize)>;
+
+#[derive(Debug, Default)]
+pub struct Presets {
+    presets: HashMap<String, String>,
+}
+
+impl Presets {
+    pub fn load() -> Result<Self> {
+        // Read the preset file if there is one
+        match util::resampler_file(consts::PRESET_FILE) {
+            Some(path) if path.exists() => Self::read(&path),
+            _ => Ok(Self::default()),
+        }
+    }
+
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let text = fs::read_to_string(path)?;
+        text.parse()
+    }
+
+    fn longest_match(&self, chars: &[char]) -> Option<(&str, &str)> {
+        // longest preset name at the start of chars, so @rockg5 is @rock then g5
+        self.presets
+            .iter()
+            .filter(|(name, _)| {
+                let name: Vec<char> = name.chars().collect();
+                chars.starts_with(&name)
+            })
+            .max_by_key(|(name, _)| name.chars().count())
+            .map(|(name, flags)| (name.as_str(), flags.as_str()))
+    }
+
+    fn expand_depth(
+        &self,
+        s: &str,
+        depth: usize,
+        position: Option<usize>,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> (Located, Located) {
+        // split a flag string into the expanded presets and the explicit flags, with the position
+        // every character came from. problems inside presets are reported at the position of the
+        // preset in the flag string
+        let chars: Vec<char> = s.chars().collect();
+        let mut preset_flags = Vec::new();
+        let mut explicit = Vec::new();
+        let mut i = 0;
+        while i < chars.len() {
+            let at = position.unwrap_or(i);
+            if chars[i] != '@' {
+                explicit.push((chars[i], at));
+                i += 1;
+                continue;
+            }
+            let Some((name, flags)) = self.longest_match(&chars[i + 1..]) else {
+                let name: String = chars[i + 1..]
+                    .iter()
+                    .take_while(|c| c.is_alphanumeric() || **c == '_')
+                    .collect();
+                diagnostics.push(Diagnostic::error(at, format!("unknown preset `@{name}`")));
+                i += 1 + name.chars().count();
+                continue;
+            };
+            if depth >= MAX_PRESET_DEPTH {
+                diagnostics.push(Diagnostic::error(
+                    at,
+                    format!("preset `@{name}` uses itself"),
+                ));
+                return (preset_flags, explicit);
+            }
+            let (inner_presets, inner_explicit) =
+                self.expand_depth(flags, depth + 1, Some(at), diagnostics);
+            preset_flags.extend(inner_presets);
+            preset_flags.extend(inner_explicit);
+            i += 1 + name.chars().count();
+        }
+        (preset_flags, explicit)
+    }
+
+    pub fn expand(&self, s: &str) -> (String, Vec<usize>, Vec<Diagnostic>) {
+        // Replace presets with their flags. Presets go first so explicit flags override them.
+        // Also gives the position in s of every character, flags from presets point at their @
+        let mut diagnostics = Vec::new();
+        let (preset_flags, explicit) = self.expand_depth(s, 0, None, &mut diagnostics);
+        diagnostics.dedup_by(|a, b| a.to_string() == b.to_string());
+        let (expanded, positions) = preset_flags.into_iter().chain(explicit).unzip();
+        (expanded, positions, diagnostics)
+    }
+}
+
+impl FromStr for Presets {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        // one preset per line as name = flags, lines starting with # are comments
+        let mut presets = HashMap::new();
+        for (i, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let Some((name, flags)) = line.split_once('=') else {
+                return Err(anyhow!("Preset file line {} has no `=`.", i + 1));
+            };
+            let name = name.trim().trim_start_matches('@');
+            if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+                return Err(anyhow!("Preset file line {} has an invalid name.", i + 1));
+            }
+            presets.insert(name.to_string(), flags.trim().to_string());
+        }
+        Ok(Self { presets })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Presets;
+    use crate::flags::parser::Flags;
+
+    #[test]
+    fn test_presets() {
+        let presets = "
+            # team presets
+            rock = g-20B60P90gw15
+            rock_soft = @rock B80
+            loop = e1
+            "
+        .parse::<Presets>()
+        .expect("Cannot parse presets");
+
+        // explicit flags override presets wherever they are written
+        let (expanded, positions, diagnostics) = presets.expand("g-5@rock");
+        assert!(diagnostics.is_empty());
+        assert_eq!(expanded, "g-20B60P90gw15g-5");
+        assert_eq!(positions, [vec![3; 14], vec![0, 1, 2]].concat());
+        let (flags, _) = Flags::parse_with_diagnostics(&expanded);
+        assert_eq!(flags.gender, -5.);
+        assert_eq!(flags.growl, 15.);
+
+        // presets using presets, longest name wins, names can be followed by flags
+        let (expanded, _, _) = presets.expand("@rock_softt10@loop");
+        assert_eq!(expanded, "g-20B60P90gw15 B80e1t10");
+        let (expanded, _, _) = presets.expand("@rockt10");
+        assert_eq!(expanded, "g-20B60P90gw15t10");
+
+        // unknown presets and loops are errors
+        let (expanded, _, diagnostics) = presets.expand("B70@pop/g5");
+        assert_eq!(expanded, "B70/g5");
+        assert_eq!(diagnostics[0].to_string(), "unknown preset `@pop` at 3");
+
+        let looped: Presets = "a = @b\nb = @a".parse().unwrap();
+        let (_, _, diagnostics) = looped.expand("g1@a");
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].to_string(), "preset `@a` uses itself at 2");
+
+        assert!("rock g5".parse::<Presets>().is_err());
+        assert!("ro ck = g5".parse::<Presets>().is_err());
+    }
+}
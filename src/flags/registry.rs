@@ -0,0 +1,953 @@
+use crate::consts;
+use crate::flags::parser::Flags;
+use crate::interpolator::interp::InterpolatorType;
+use crate::quantize::Scale;
+use crate::timing::{ConsonantMode, StretchMode};
+
+const INF: f64 = f64::INFINITY;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FlagGroup {
+    Fry,
+    Devoice,
+    Vibrato,
+    Other,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FlagValues {
+    // any value in the recommended range, shown as a slider in OpenUtau
+    Range(f64, f64),
+    // a few values, shown as a dropdown in OpenUtau
+    Choices(&'static [f64]),
+}
+
+#[derive(Debug)]
+pub struct FlagSpec {
+    // flag as written in the flag string
+    pub flag: &'static str,
+    // OpenUtau expression abbreviation and name
+    pub abbr: &'static str,
+    pub name: &'static str,
+    pub group: FlagGroup,
+    pub unit: &'static str,
+    // value the flag has when it's not in the flag string. None means the flag is off
+    pub default: Option<f64>,
+    // value the flag gets when no number follows it. None means a number is required
+    pub option_value: Option<f64>,
+    // values outside of this get clamped
+    pub range: (f64, f64),
+    // only whole numbers are meaningful, other values get rounded
+    pub integer: bool,
+    pub recommended: FlagValues,
+    // the flag changes the sound of the sample, so notes using it can't skip resynthesis
+    pub changes_timbre: bool,
+    pub description: &'static str,
+    pub note: Option<&'static str>,
+    pub set: fn(&mut Flags, f64),
+}
+
+// Every flag straycat-rs understands. Parsing, the OpenUtau manifest and the docs all come from here
+pub static FLAGS: [FlagSpec; 41] = [
+    FlagSpec {
+        flag: "fe",
+        abbr: "frye",
+        name: "vocal fry length",
+        group: FlagGroup::Fry,
+        unit: "milliseconds",
+        default: Some(0.),
+        option_value: None,
+        range: (-INF, INF),
+        integer: false,
+        recommended: FlagValues::Range(-1000., 1000.),
+        changes_timbre: true,
+        description: "Enables this behavior and sets the length of the fry area. Positive values put the fry area to the left of the pivot, negative values to the right.",
+        note: None,
+        set: |flags, value| flags.fry_enable = value,
+    },
+    FlagSpec {
+        flag: "fo",
+        abbr: "fryo",
+        name: "vocal fry offset",
+        group: FlagGroup::Fry,
+        unit: "milliseconds",
+        default: Some(0.),
+        option_value: None,
+        range: (-INF, INF),
+        integer: false,
+        recommended: FlagValues::Range(-1000., 1000.),
+        changes_timbre: false,
+        description: "Moves the pivot, which is centered around the consonant point of the oto. Positive values move the pivot to the right, negative values to the left.",
+        note: None,
+        set: |flags, value| flags.fry_offset = value,
+    },
+    FlagSpec {
+        flag: "fl",
+        abbr: "fryl",
+        name: "vocal fry transition length",
+        group: FlagGroup::Fry,
+        unit: "milliseconds",
+        default: Some(75.),
+        option_value: None,
+        range: (1., INF),
+        integer: false,
+        recommended: FlagValues::Range(1., 250.),
+        changes_timbre: false,
+        description: "Changes the length of the transition to the fry. Lower values mean a faster transition.",
+        note: None,
+        set: |flags, value| flags.fry_transition = value,
+    },
+    FlagSpec {
+        flag: "fv",
+        abbr: "fryv",
+        name: "vocal fry volume",
+        group: FlagGroup::Fry,
+        unit: "percentage",
+        default: Some(10.),
+        option_value: None,
+        range: (0., 100.),
+        integer: false,
+        recommended: FlagValues::Range(0., 100.),
+        changes_timbre: false,
+        description: "The volume of the fry area.",
+        note: None,
+        set: |flags, value| flags.fry_volume = value,
+    },
+    FlagSpec {
+        flag: "fp",
+        abbr: "fryp",
+        name: "vocal fry pitch",
+        group: FlagGroup::Fry,
+        unit: "Hertz",
+        default: Some(consts::F0_FLOOR),
+        option_value: None,
+        range: (0., INF),
+        integer: false,
+        recommended: FlagValues::Range(0., consts::F0_FLOOR),
+        changes_timbre: false,
+        description: "The pitch of the fry.",
+        note: None,
+        set: |flags, value| flags.fry_pitch = value,
+    },
+    FlagSpec {
+        flag: "ve",
+        abbr: "dvxe",
+        name: "devoice length",
+        group: FlagGroup::Devoice,
+        unit: "milliseconds",
+        default: Some(0.),
+        option_value: None,
+        range: (-INF, INF),
+        integer: false,
+        recommended: FlagValues::Range(-1000., 1000.),
+        changes_timbre: true,
+        description: "Enables this behavior and sets the length of the unvoiced area. Positive values put the unvoiced area to the left of the pivot, negative values to the right.",
+        note: None,
+        set: |flags, value| flags.devoice_enable = value,
+    },
+    FlagSpec {
+        flag: "vo",
+        abbr: "dvxo",
+        name: "devoice offset",
+        group: FlagGroup::Devoice,
+        unit: "milliseconds",
+        default: Some(0.),
+        option_value: None,
+        range: (-INF, INF),
+        integer: false,
+        recommended: FlagValues::Range(-1000., 1000.),
+        changes_timbre: false,
+        description: "Moves the pivot, which is centered around the consonant point of the oto. Positive values move the pivot to the right, negative values to the left.",
+        note: None,
+        set: |flags, value| flags.devoice_offset = value,
+    },
+    FlagSpec {
+        flag: "vl",
+        abbr: "dvxl",
+        name: "devoice transition length",
+        group: FlagGroup::Devoice,
+        unit: "milliseconds",
+        default: Some(75.),
+        option_value: None,
+        range: (1., INF),
+        integer: false,
+        recommended: FlagValues::Range(1., 1000.),
+        changes_timbre: false,
+        description: "Changes the length of the transition. Lower values mean a faster transition.",
+        note: None,
+        set: |flags, value| flags.devoice_transition = value,
+    },
+    FlagSpec {
+        flag: "vd",
+        abbr: "vibd",
+        name: "vibrato depth",
+        group: FlagGroup::Vibrato,
+        unit: "cents",
+        default: Some(0.),
+        option_value: None,
+        range: (0., INF),
+        integer: false,
+        recommended: FlagValues::Range(0., 100.),
+        changes_timbre: true,
+        description: "Enables this behavior and sets how far the vibrato goes above and below the pitch. It is added on top of the pitchbend.",
+        note: None,
+        set: |flags, value| flags.vibrato_depth = value,
+    },
+    FlagSpec {
+        flag: "vr",
+        abbr: "vibr",
+        name: "vibrato rate",
+        group: FlagGroup::Vibrato,
+        unit: "Hertz",
+        default: Some(5.5),
+        option_value: None,
+        range: (0., INF),
+        integer: false,
+        recommended: FlagValues::Range(3., 9.),
+        changes_timbre: false,
+        description: "How many times per second the vibrato goes up and down.",
+        note: None,
+        set: |flags, value| flags.vibrato_rate = value,
+    },
+    FlagSpec {
+        flag: "vs",
+        abbr: "vibs",
+        name: "vibrato delay",
+        group: FlagGroup::Vibrato,
+        unit: "milliseconds",
+        default: Some(150.),
+        option_value: None,
+        range: (-INF, INF),
+        integer: false,
+        recommended: FlagValues::Range(0., 1000.),
+        changes_timbre: false,
+        description: "When the vibrato starts, measured from the consonant point of the oto.",
+        note: None,
+        set: |flags, value| flags.vibrato_delay = value,
+    },
+    FlagSpec {
+        flag: "vf",
+        abbr: "vibf",
+        name: "vibrato fade-in",
+        group: FlagGroup::Vibrato,
+        unit: "milliseconds",
+        default: Some(150.),
+        option_value: None,
+        range: (0., INF),
+        integer: false,
+        recommended: FlagValues::Range(0., 1000.),
+        changes_timbre: false,
+        description: "How long the vibrato takes to reach its full depth after it starts.",
+        note: None,
+        set: |flags, value| flags.vibrato_fade = value,
+    },
+    FlagSpec {
+        flag: "vj",
+        abbr: "vibj",
+        name: "vibrato drift",
+        group: FlagGroup::Vibrato,
+        unit: "percentage",
+        default: Some(0.),
+        option_value: None,
+        range: (0., 100.),
+        integer: false,
+        recommended: FlagValues::Range(0., 100.),
+        changes_timbre: false,
+        description: "Lets the rate and depth of the vibrato wander slowly so it sounds less mechanical. The drift is the same every time the note is rendered unless the `R` flag changes the seed.",
+        note: None,
+        set: |flags, value| flags.vibrato_drift = value,
+    },
+    FlagSpec {
+        flag: "G",
+        abbr: "rgn",
+        name: "regenerate features",
+        group: FlagGroup::Other,
+        unit: "percentage",
+        default: None,
+        option_value: Some(consts::D4C_THRESHOLD * 100.),
+        range: (0., 100.),
+        integer: false,
+        recommended: FlagValues::Choices(&[10., 20., 30., 40., 50., 60., 70., 80., 90., 100.]),
+        changes_timbre: false,
+        description: "Regenerates the `.sc` file that straycat-rs generates to cache WORLD features in. The value set for this flag controls the devoicing threshold of WORLD's spectral analysis. Can also be used as an option flag.",
+        note: None,
+        set: |flags, value| flags.generate_features = Some(value),
+    },
+    FlagSpec {
+        flag: "g",
+        abbr: "sgen",
+        name: "gender",
+        group: FlagGroup::Other,
+        unit: "10 units = 1 semitone",
+        default: Some(0.),
+        option_value: None,
+        range: (-INF, INF),
+        integer: false,
+        recommended: FlagValues::Range(-120., 120.),
+        changes_timbre: true,
+        description: "Shifts the formants of the render, commonly known as \"gender.\" Higher values makes a more \"masculine\" quality, lower values makes a more \"feminine\" quality.",
+        note: Some("OpenUtau has gender/GEN set as an expression for this flag with range [-100, 100]."),
+        set: |flags, value| flags.gender = value,
+    },
+    FlagSpec {
+        flag: "B",
+        abbr: "sbre",
+        name: "breathiness",
+        group: FlagGroup::Other,
+        unit: "percentage",
+        default: Some(50.),
+        option_value: None,
+        range: (0., 100.),
+        integer: false,
+        recommended: FlagValues::Range(0., 100.),
+        changes_timbre: true,
+        description: "Controls the breathiness of the render. 100 produces a whisper only render.",
+        note: Some("OpenUtau has breath/BRE set as an expression for this flag with 0 for the default."),
+        set: |flags, value| flags.breathiness = value,
+    },
+    FlagSpec {
+        flag: "P",
+        abbr: "pcmp",
+        name: "peak compressor",
+        group: FlagGroup::Other,
+        unit: "percentage",
+        default: Some(86.),
+        option_value: None,
+        range: (0., 100.),
+        integer: false,
+        recommended: FlagValues::Range(0., 99.),
+        changes_timbre: false,
+        description: "Compresses the render based on the peak. Lower values give a stronger compression. 0 disables this.",
+        note: None,
+        set: |flags, value| flags.peak_compression = value,
+    },
+    FlagSpec {
+        flag: "p",
+        abbr: "pnrm",
+        name: "peak normalization",
+        group: FlagGroup::Other,
+        unit: "dB (negated)",
+        default: Some(4.),
+        option_value: None,
+        range: (-INF, INF),
+        integer: false,
+        recommended: FlagValues::Range(-1., 6.),
+        changes_timbre: false,
+        description: "Normalizes the render based on the peak after compression. Higher values leads to a quieter normalization as the input is negated. Negative values disable this.",
+        note: None,
+        set: |flags, value| flags.peak_normalization = value,
+    },
+    FlagSpec {
+        flag: "t",
+        abbr: "foff",
+        name: "pitch offset",
+        group: FlagGroup::Other,
+        unit: "cents",
+        default: Some(0.),
+        option_value: None,
+        range: (-INF, INF),
+        integer: false,
+        recommended: FlagValues::Range(-100., 100.),
+        changes_timbre: false,
+        description: "Applies an offset to the pitch of the note. Positive values offsets the pitch up, negative values down.",
+        note: None,
+        set: |flags, value| flags.pitch_offset = value,
+    },
+    FlagSpec {
+        flag: "Tr",
+        abbr: "tref",
+        name: "tuning reference",
+        group: FlagGroup::Other,
+        unit: "Hertz",
+        default: None,
+        option_value: None,
+        range: (1., INF),
+        integer: false,
+        recommended: FlagValues::Range(400., 480.),
+        changes_timbre: false,
+        description: "The frequency of the reference key of the tuning, A4 unless a keymap sets another key. Without this flag A4 is 440 Hz or the keymap's frequency.",
+        note: None,
+        set: |flags, value| flags.tuning_reference = Some(value),
+    },
+    FlagSpec {
+        flag: "Te",
+        abbr: "tedo",
+        name: "equal division of the octave",
+        group: FlagGroup::Other,
+        unit: "steps per octave",
+        default: None,
+        option_value: None,
+        range: (1., INF),
+        integer: true,
+        recommended: FlagValues::Range(5., 72.),
+        changes_timbre: false,
+        description: "Tunes every key one step of this many equal steps per octave apart instead of 12, keeping the reference key in place. Overrides a Scala scale file. Only the note and pitchbend are tuned, the t flag, modulation and vibrato stay in cents.",
+        note: None,
+        set: |flags, value| flags.tuning_edo = Some(value),
+    },
+    FlagSpec {
+        flag: "I",
+        abbr: "pint",
+        name: "pitch interpolation",
+        group: FlagGroup::Other,
+        unit: "mode",
+        default: Some(0.),
+        option_value: None,
+        range: (-INF, INF),
+        integer: true,
+        recommended: FlagValues::Choices(&[1., 2., 3., 4.]),
+        changes_timbre: false,
+        description: "Chooses how the pitchbend is smoothed between its points. 0 is Akima, which can overshoot on sharp pitch steps. 1 is Catmull-Rom and 2 is Lanczos. 3 is a monotone cubic (PCHIP) that never goes past the points. 4 connects the points with straight lines.",
+        note: None,
+        set: |flags, value| flags.pitch_interpolation = InterpolatorType::from_value(value),
+    },
+    FlagSpec {
+        flag: "A",
+        abbr: "trem",
+        name: "tremolo",
+        group: FlagGroup::Other,
+        unit: "percentage",
+        default: Some(0.),
+        option_value: None,
+        range: (-INF, INF),
+        integer: false,
+        recommended: FlagValues::Range(-100., 100.),
+        changes_timbre: true,
+        description: "Adds tremolo to the note based on the pitchbend. Negative values flip the envelope. Only vibratos between `Al` and `Ah` are followed.",
+        note: None,
+        set: |flags, value| flags.tremolo = value,
+    },
+    FlagSpec {
+        flag: "Av",
+        abbr: "trmv",
+        name: "tremolo follows vibrato",
+        group: FlagGroup::Other,
+        unit: "toggle",
+        default: Some(0.),
+        option_value: Some(1.),
+        range: (-INF, INF),
+        integer: false,
+        recommended: FlagValues::Choices(&[1.]),
+        changes_timbre: false,
+        description: "Makes the `A` tremolo also follow the vibrato generated by the vibrato flags, not only the pitchbend. Can also be used as an option flag.",
+        note: None,
+        set: |flags, value| flags.tremolo_follow = value != 0.,
+    },
+    FlagSpec {
+        flag: "Al",
+        abbr: "trml",
+        name: "tremolo band low edge",
+        group: FlagGroup::Other,
+        unit: "Hertz",
+        default: Some(4.),
+        option_value: None,
+        range: (0.5, 50.),
+        integer: false,
+        recommended: FlagValues::Range(1., 10.),
+        changes_timbre: false,
+        description: "The slowest vibrato the `A` tremolo follows. Lower it for slow vibratos.",
+        note: None,
+        set: |flags, value| flags.tremolo_low = value,
+    },
+    FlagSpec {
+        flag: "Ah",
+        abbr: "trmh",
+        name: "tremolo band high edge",
+        group: FlagGroup::Other,
+        unit: "Hertz",
+        default: Some(8.),
+        option_value: None,
+        range: (0.5, 50.),
+        integer: false,
+        recommended: FlagValues::Range(4., 16.),
+        changes_timbre: false,
+        description: "The fastest vibrato the `A` tremolo follows. Raise it for fast vibratos.",
+        note: None,
+        set: |flags, value| flags.tremolo_high = value,
+    },
+    FlagSpec {
+        flag: "Ad",
+        abbr: "lfod",
+        name: "tremolo LFO depth",
+        group: FlagGroup::Other,
+        unit: "percentage",
+        default: Some(0.),
+        option_value: None,
+        range: (0., 100.),
+        integer: false,
+        recommended: FlagValues::Range(0., 100.),
+        changes_timbre: true,
+        description: "Adds tremolo from its own oscillator, without needing a vibrato in the pitchbend. The volume dips by this much at the bottom of each cycle. Works alongside `A`.",
+        note: None,
+        set: |flags, value| flags.tremolo_depth = value,
+    },
+    FlagSpec {
+        flag: "Ar",
+        abbr: "lfor",
+        name: "tremolo LFO rate",
+        group: FlagGroup::Other,
+        unit: "Hertz",
+        default: Some(5.5),
+        option_value: None,
+        range: (0., INF),
+        integer: false,
+        recommended: FlagValues::Range(1., 12.),
+        changes_timbre: false,
+        description: "How many times per second the `Ad` tremolo dips.",
+        note: None,
+        set: |flags, value| flags.tremolo_rate = value,
+    },
+    FlagSpec {
+        flag: "Ap",
+        abbr: "lfop",
+        name: "tremolo LFO phase",
+        group: FlagGroup::Other,
+        unit: "degrees",
+        default: Some(0.),
+        option_value: None,
+        range: (-INF, INF),
+        integer: false,
+        recommended: FlagValues::Range(0., 360.),
+        changes_timbre: false,
+        description: "Where the `Ad` tremolo's cycle is at the consonant point. 0 starts at full volume, 180 starts in a dip.",
+        note: None,
+        set: |flags, value| flags.tremolo_phase = value,
+    },
+    FlagSpec {
+        flag: "gw",
+        abbr: "grwl",
+        name: "growl",
+        group: FlagGroup::Other,
+        unit: "percentage",
+        default: Some(0.),
+        option_value: None,
+        range: (0., 100.),
+        integer: false,
+        recommended: FlagValues::Range(0., 100.),
+        changes_timbre: true,
+        description: "Adds a faked growl to the render. 100 is similar to [death growl](https://en.wikipedia.org/wiki/Death_growl).",
+        note: None,
+        set: |flags, value| flags.growl = value,
+    },
+    FlagSpec {
+        flag: "S",
+        abbr: "apmx",
+        name: "aperiodic mix",
+        group: FlagGroup::Other,
+        unit: "percentage",
+        default: Some(0.),
+        option_value: None,
+        range: (0., 100.),
+        integer: false,
+        recommended: FlagValues::Range(0., 100.),
+        changes_timbre: true,
+        description: "Mixes a render where the aperiodicity is maxed out. This produces an almost growl-like whispery tone which can complement the growl flag.",
+        note: None,
+        set: |flags, value| flags.aperiodic_mix = value,
+    },
+    FlagSpec {
+        flag: "O",
+        abbr: "hybc",
+        name: "original consonant crossfade",
+        group: FlagGroup::Other,
+        unit: "milliseconds",
+        default: Some(0.),
+        option_value: Some(30.),
+        range: (0., INF),
+        integer: false,
+        recommended: FlagValues::Range(0., 100.),
+        changes_timbre: false,
+        description: "Keeps the original audio of the sample before the consonant point instead of the WORLD render, then crossfades into the render. This keeps plosives and fricatives crisp. Velocity still stretches the original audio. The value sets the length of the crossfade. Can also be used as an option flag, which uses a 30 ms crossfade. Flags that change the timbre do not affect the original audio.",
+        note: None,
+        set: |flags, value| flags.hybrid_consonant = value,
+    },
+    FlagSpec {
+        flag: "e",
+        abbr: "strm",
+        name: "stretch mode",
+        group: FlagGroup::Other,
+        unit: "mode",
+        default: Some(0.),
+        option_value: Some(1.),
+        range: (-INF, INF),
+        integer: true,
+        recommended: FlagValues::Choices(&[1., 2.]),
+        changes_timbre: false,
+        description: "Chooses how notes longer than the sample are stretched. Only the steadiest part after the consonant is stretched, the onset and release play at their natural speed. 0 slows that part down. 1 loops it at its natural speed with a crossfade at the loop point. 2 plays it back and forth at its natural speed. Can also be used as an option flag, which loops.",
+        note: None,
+        set: |flags, value| flags.stretch_mode = StretchMode::from_value(value),
+    },
+    FlagSpec {
+        flag: "H",
+        abbr: "hnms",
+        name: "harmonic plus noise synthesis",
+        group: FlagGroup::Other,
+        unit: "toggle",
+        default: Some(0.),
+        option_value: Some(1.),
+        range: (-INF, INF),
+        integer: false,
+        recommended: FlagValues::Choices(&[1.]),
+        changes_timbre: true,
+        description: "Renders with the built-in harmonic plus noise synthesizer instead of WORLD. It keeps the harmonics phase-coherent, which can sound cleaner on high notes. Can also be used as an option flag. `H0` uses WORLD.",
+        note: None,
+        set: |flags, value| flags.harmonic_noise = value != 0.,
+    },
+    FlagSpec {
+        flag: "V",
+        abbr: "vari",
+        name: "variation",
+        group: FlagGroup::Other,
+        unit: "percentage",
+        default: Some(0.),
+        option_value: None,
+        range: (0., 100.),
+        integer: false,
+        recommended: FlagValues::Range(0., 100.),
+        changes_timbre: true,
+        description: "Adds slow random drift to the pitch, spectral envelope and breathiness of notes that are stretched longer than the sample, so long notes do not sound frozen. The amount of drift is measured from the sample itself. The drift is the same every time the note is rendered.",
+        note: None,
+        set: |flags, value| flags.variation = value,
+    },
+    FlagSpec {
+        flag: "c",
+        abbr: "cnsm",
+        name: "consonant velocity mode",
+        group: FlagGroup::Other,
+        unit: "mode",
+        default: Some(0.),
+        option_value: None,
+        range: (-INF, INF),
+        integer: true,
+        recommended: FlagValues::Choices(&[1., 2., 3.]),
+        changes_timbre: false,
+        description: "Chooses how velocity stretches the consonant. 0 stretches the whole consonant evenly. 1 only stretches the closure, the quiet part before a plosive. 2 stretches everything except bursts, so plosives stay sharp. 3 stretches the start of the consonant the most and eases into natural speed towards the vowel. Falls back to 0 when the sample has nothing to stretch in that mode.",
+        note: None,
+        set: |flags, value| flags.consonant_mode = ConsonantMode::from_value(value),
+    },
+    FlagSpec {
+        flag: "R",
+        abbr: "seed",
+        name: "random seed",
+        group: FlagGroup::Other,
+        unit: "number",
+        default: None,
+        option_value: None,
+        range: (0., INF),
+        integer: true,
+        recommended: FlagValues::Range(0., 1000.),
+        changes_timbre: false,
+        description: "Sets the seed of everything random in the render: the growl, the variation and the noise of the harmonic plus noise synthesizer. Without this flag the seed comes from the sample and the note, so rendering the same note twice gives the same audio. Change it to get a different take of the same note.",
+        note: None,
+        set: |flags, value| flags.seed = Some(value as u64),
+    },
+    FlagSpec {
+        flag: "q",
+        abbr: "qntz",
+        name: "pitch quantize",
+        group: FlagGroup::Other,
+        unit: "percentage",
+        default: Some(0.),
+        option_value: None,
+        range: (0., 100.),
+        integer: false,
+        recommended: FlagValues::Range(0., 100.),
+        changes_timbre: true,
+        description: "Pulls the pitch of the render towards the nearest key of the tuning, or the nearest note of the scale set with `qk`. 100 snaps it fully for a hard-tuned sound. It is applied after the pitchbend and modulation.",
+        note: None,
+        set: |flags, value| flags.quantize = value,
+    },
+    FlagSpec {
+        flag: "qs",
+        abbr: "qspd",
+        name: "pitch quantize retune speed",
+        group: FlagGroup::Other,
+        unit: "milliseconds",
+        default: Some(0.),
+        option_value: None,
+        range: (0., INF),
+        integer: false,
+        recommended: FlagValues::Range(0., 400.),
+        changes_timbre: false,
+        description: "How fast the quantizer follows the pitch. 0 snaps instantly. Higher values only correct slow drift and let vibrato and scoops through.",
+        note: None,
+        set: |flags, value| flags.quantize_speed = value,
+    },
+    FlagSpec {
+        flag: "qk",
+        abbr: "qscl",
+        name: "pitch quantize scale",
+        group: FlagGroup::Other,
+        unit: "mode",
+        default: Some(0.),
+        option_value: None,
+        range: (-INF, INF),
+        integer: true,
+        recommended: FlagValues::Choices(&[1., 2.]),
+        changes_timbre: false,
+        description: "The scale the quantizer snaps to. 0 uses every key. 1 uses the major scale and 2 the natural minor scale of the root set with `qr`. Scales pick keys like on a piano, so with a custom tuning they snap to the tuned pitch of those keys.",
+        note: None,
+        set: |flags, value| flags.quantize_scale = Scale::from_value(value),
+    },
+    FlagSpec {
+        flag: "qr",
+        abbr: "qrot",
+        name: "pitch quantize root",
+        group: FlagGroup::Other,
+        unit: "semitones above C",
+        default: Some(0.),
+        option_value: None,
+        range: (0., 11.),
+        integer: false,
+        recommended: FlagValues::Range(0., 11.),
+        changes_timbre: false,
+        description: "The root note of the quantizer's scale, 0 is C, 2 is D and so on.",
+        note: None,
+        set: |flags, value| flags.quantize_root = value,
+    },
+];
+
+pub fn find_flag(chars: &[char]) -> Option<&'static FlagSpec> {
+    // Flag at the start of chars, two character flags first so gw isn't read as g
+    let two_chars: String = chars.iter().take(2).collect();
+    let one_char: String = chars.iter().take(1).collect();
+    FLAGS
+        .iter()
+        .find(|spec| spec.flag.len() == 2 && spec.flag == two_chars)
+        .or_else(|| {
+            FLAGS
+                .iter()
+                .find(|spec| spec.flag.len() == 1 && spec.flag == one_char)
+        })
+}
+
+fn choice_flag(spec: &FlagSpec, value: f64) -> String {
+    // how a choice is written in the flag string
+    if spec.default == Some(value) {
+        String::new()
+    } else if spec.option_value == Some(value) {
+        spec.flag.to_string()
+    } else {
+        format!("{}{}", spec.flag, value)
+    }
+}
+
+pub fn manifest() -> String {
+    // OpenUtau resampler manifest with every flag as an expression
+    let mut yaml = String::from("expressions:\n");
+    for spec in FLAGS.iter() {
+        yaml += &format!("  {}:\n", spec.abbr);
+        yaml += &format!("    name: {}\n", spec.name);
+        yaml += &format!("    abbr: {}\n", spec.abbr);
+        match spec.recommended {
+            FlagValues::Range(min, max) => {
+                yaml += "    type: Numerical\n";
+                yaml += &format!("    min: {min}\n");
+                yaml += &format!("    max: {max}\n");
+                // flags without a default depend on something else, like the keymap or the note
+                if let Some(default) = spec.default {
+                    yaml += &format!("    default_value: {default}\n");
+                }
+                yaml += "    is_flag: true\n";
+                yaml += &format!("    flag: {}\n", spec.flag);
+            }
+            FlagValues::Choices(choices) => {
+                yaml += "    type: Options\n";
+                yaml += "    is_flag: true\n";
+                yaml += "    options:\n";
+                yaml += "    - ''\n";
+                for choice in choices {
+                    yaml += &format!("    - {}\n", choice_flag(spec, *choice));
+                }
+            }
+        }
+    }
+    yaml
+}
+
+fn format_range(min: f64, max: f64) -> String {
+    // interval notation, infinite ends are open
+    let bound = |x: f64| {
+        if x.is_infinite() {
+            format!("{}inf", if x < 0. { "-" } else { "+" })
+        } else {
+            format!("{x}")
+        }
+    };
+    let open = if min.is_infinite() { "(" } else { "[" };
+    let close = if max.is_infinite() { ")" } else { "]" };
+    format!("{open}{}, {}{close}", bound(min), bound(max))
+}
+
+fn format_set(spec: &FlagSpec, choices: &[f64]) -> String {
+    // set notation, with the default first if it's not one of the choices
+    let values: Vec<String> = spec
+        .default
+        .into_iter()
+        .chain(choices.iter().copied())
+        .map(|x| format!("{x}"))
+        .collect();
+    format!("{{{}}}", values.join(", "))
+}
+
+const DOCS_INTRO: &str = r#"# straycat-rs flags
+ This is the main documentation of the flags available in straycat-rs.
+
+## Value Notation for ranges
+ The notation for the ranges of values follows [interval notation](https://en.wikipedia.org/wiki/Interval_(mathematics)#Notations_for_intervals) from mathematics. Flags accept decimals like `g-2.5` and scientific notation with an uppercase E like `t1.5E1`. Lowercase e is not used for exponents since it is the stretch mode flag. Here is a summary of this notation:
+ - Square brackets **[]** means the value is included in the range.
+ - Parentheses **()** means the value is not included in the range.
+ - **inf** means infinity. The sign indicates its direction.
+ - Curly brackets **{}** list every value the flag uses.
+
+ Example: [0, 100) means a range from 0 to 100, including 0 but not including 100.
+
+## Problems in flag strings
+ Unknown characters, numbers without a flag and flags that need a value but have none are reported with their position in the flag string and skipped. Values outside of a flag's range are clamped with a warning. Running straycat-rs with `--strict` makes these errors fail the render instead. Clamped values are still only warnings.
+
+## Presets
+ Flag strings that are used often can be saved as presets in a `straycat-rs.presets` file next to the resampler. Each line is a name and a flag string, presets can use other presets and lines starting with `#` are comments.
+ ```
+ # rock voice
+ rock = g-20B60P90gw15
+ rock_loop = @rock e1
+ ```
+ Write `@rock` in the flags of a note to use it. Flags written next to a preset override it, so `@rockg-5` uses the rock preset with `g-5`. The render log shows the flag string after presets are expanded.
+
+## Tuning
+ Notes are tuned to 12 equal steps per octave with A4 at 440 Hz unless told otherwise. `Tr` changes the reference frequency and `Te` switches to another equal division of the octave. For other tunings, put a [Scala](https://www.huygens-fokker.org/scala/scl_format.html) scale file named `straycat-rs.scl` next to the resampler, and optionally a keymap named `straycat-rs.kbm`, or pass them with `--scl` and `--kbm`. Pitchbends glide between the tuned keys, so a bend of 100 cents always reaches the next key.
+
+## Descriptors
+ The behavior of the flags will be explained through a table with descriptors. The columns for these tables mean the following:
+ | Column | Explanation |
+ | :----: | :---------- |
+ |  Flag  | The flag associated with the behavior. |
+ | Description | A description of the behavior of the flag. |
+ | Unit | The unit of the value related to the flag. |
+ | Default | The default value of the flag. |
+ | Value Range | The range of accepted values for the flag. |
+ | Recommended Range | The recommended range of values for each flag. Helpful for OpenUtau users.|
+
+ This file and `straycat-rs.yaml` are generated with `straycat-rs flag-docs` and `straycat-rs manifest`.
+
+# Flags
+"#;
+
+const DOCS_CURVES: &str = r#"
+# Expression curves
+ Some flags can change within a note through a sidecar file. If a JSON file with the same name as the output file exists (`out.wav` reads `out.json`), straycat-rs reads curves from it and uses them in place of the flag values. Each curve is a list of `[time, value]` points, with time in milliseconds from the start of the render. Values are linearly interpolated and the first and last values are held.
+ | Curve | Replaces | Unit |
+ | :---: | :------: | :--: |
+ | `gender` | `g` | 1/120 of an octave |
+ | `breathiness` | `B` | % |
+ | `growl` | `gw` | % |
+ | `tension` | - | brightness from -100 to 100, 100 is +6 dB per octave above 1 kHz |
+ | `volume` | note volume | % |
+
+ Example: `{"gender": [[0, 0], [500, 30]], "volume": [[0, 100], [800, 60]]}`
+"#;
+
+pub fn flag_docs() -> String {
+    // Markdown documentation of every flag
+    let mut md = String::from(DOCS_INTRO);
+    let mut notes = Vec::new();
+    for (group, header) in [
+        (
+            FlagGroup::Fry,
+            "### Vocal fry flag set\n This set of flags allow producing fake vocal fries or glottal stops by adding a dip in the pitchbend that you cannot normally reach due to limitations of how resamplers receive pitchbends.\n",
+        ),
+        (
+            FlagGroup::Devoice,
+            "\n### Devoicing flag set\n This set of flags allow setting a specific area into a whispery sound, allowing the creation of fake end breaths.\n",
+        ),
+        (
+            FlagGroup::Vibrato,
+            "\n### Vibrato flag set\n This set of flags adds vibrato to the render without drawing it in the pitchbend.\n",
+        ),
+        (
+            FlagGroup::Other,
+            "\n### Other flags\n These are other flags in straycat-rs that work individually.\n",
+        ),
+    ] {
+        md += header;
+        md += " | Flag | Description | Unit | Default | Value Range | Recommended Range |\n";
+        md += " | :--: | :---------- | :--: | :-----: | :---: | :------------------------: |\n";
+        for spec in FLAGS.iter().filter(|spec| spec.group == group) {
+            let (value_range, recommended) = match spec.recommended {
+                FlagValues::Range(min, max) => (
+                    format_range(spec.range.0, spec.range.1),
+                    format_range(min, max),
+                ),
+                FlagValues::Choices(choices) if spec.range.0.is_infinite() => {
+                    (format_set(spec, choices), format_set(spec, choices))
+                }
+                FlagValues::Choices(choices) => (
+                    format_range(spec.range.0, spec.range.1),
+                    format_set(spec, choices),
+                ),
+            };
+            let note = match spec.note {
+                Some(note) => {
+                    notes.push(note);
+                    format!("[^{}]", notes.len())
+                }
+                None => String::new(),
+            };
+            let default = match spec.default.or(spec.option_value) {
+                Some(default) => default.to_string(),
+                None => String::from("-"),
+            };
+            md += &format!(
+                " | `{}` | {} | {} | {} | {} | {}{} |\n",
+                spec.flag, spec.description, spec.unit, default, value_range, recommended, note
+            );
+        }
+    }
+    md += DOCS_CURVES;
+    for (i, note) in notes.iter().enumerate() {
+        md += &format!("\n[^{}]: {}\n", i + 1, note);
+    }
+    md
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{find_flag, flag_docs, manifest, FlagValues, FLAGS};
+
+    #[test]
+    fn test_registry() {
+        // flags are unique, defaults are within range and recommended values
+        for (i, spec) in FLAGS.iter().enumerate() {
+            assert!(FLAGS[i + 1..].iter().all(|other| other.flag != spec.flag));
+            assert!(FLAGS[i + 1..].iter().all(|other| other.abbr != spec.abbr));
+            if let Some(default) = spec.default {
+                assert!(default >= spec.range.0 && default <= spec.range.1);
+                if let FlagValues::Range(min, max) = spec.recommended {
+                    assert!(default >= min && default <= max, "{}", spec.flag);
+                }
+            }
+        }
+
+        let chars: Vec<char> = "gw10".chars().collect();
+        assert_eq!(find_flag(&chars).unwrap().flag, "gw");
+        let chars: Vec<char> = "g10".chars().collect();
+        assert_eq!(find_flag(&chars).unwrap().flag, "g");
+        let chars: Vec<char> = "Q".chars().collect();
+        assert!(find_flag(&chars).is_none());
+    }
+
+    #[test]
+    fn test_generated_files() {
+        // checked in files have to be regenerated when the flag table changes
+        assert_eq!(manifest(), include_str!("../../straycat-rs.yaml"));
+        assert_eq!(flag_docs(), include_str!("../../flag_docs.md"));
+
+        // flags without a default don't claim the bottom of their range as one
+        let manifest = manifest();
+        let tref = manifest.split("  tref:\n").nth(1).unwrap();
+        let tref = tref.split("  tedo:\n").next().unwrap();
+        assert!(tref.contains("flag: Tr\n"));
+        assert!(!tref.contains("default_value"));
+        assert!(manifest.contains("default_value: 75\n"));
+    }
+}
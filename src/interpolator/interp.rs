@@ -1,9 +1,11 @@
+use anyhow::{anyhow, Result};
+
 use crate::util;
 pub trait Interpolator {
-    // basic interpolator trait, uniform interpolation only
+    // basic interpolator trait, x is the index into the curve unless the interpolator has knots
     fn sample(&self, x: f64) -> f64;
 
-    fn sample_with_vec(&self, x: &Vec<f64>) -> Vec<f64> {
+    fn sample_with_vec(&self, x: &[f64]) -> Vec<f64> {
         x.iter().map(|p| self.sample(*p)).collect()
     }
 }
@@ -16,23 +18,123 @@ struct CubicCoefficients {
     pub d: f64,
 }
 
+impl CubicCoefficients {
+    fn eval(&self, r: f64) -> f64 {
+        self.a + self.b * r + self.c * r * r + self.d * r * r * r
+    }
+}
+
+fn check_knots(knots: &[f64], curve: &[f64], strict: bool) -> Result<()> {
+    // Knots are the x of every point and have to go left to right. Linear interpolation can
+    // take repeated knots as a jump, splines need room between them
+    if curve.is_empty() {
+        return Err(anyhow!("Interpolator needs at least one point."));
+    }
+    if knots.len() != curve.len() {
+        return Err(anyhow!(
+            "Interpolator has {} knots for {} points.",
+            knots.len(),
+            curve.len()
+        ));
+    }
+    if knots.iter().any(|x| !x.is_finite()) {
+        return Err(anyhow!("Interpolator knots must be finite."));
+    }
+    if knots
+        .windows(2)
+        .any(|w| w[1] < w[0] || (strict && w[1] == w[0]))
+    {
+        return Err(anyhow!("Interpolator knots must be in increasing order."));
+    }
+    Ok(())
+}
+
+fn spacing(knots: Option<&[f64]>, len: usize) -> Vec<f64> {
+    // width of every segment, 1 on a uniform grid
+    match knots {
+        Some(knots) => knots.windows(2).map(|w| w[1] - w[0]).collect(),
+        None => vec![1.; len.saturating_sub(1)],
+    }
+}
+
+fn locate(knots: Option<&[f64]>, len: usize, x: f64) -> (usize, f64) {
+    // Segment that holds x and how far into it x is, from 0 to 1. Clamped to the ends, which
+    // land on the first and last point
+    let last = len - 1;
+    match knots {
+        None => {
+            let x = x.clamp(0., last as f64);
+            let index = x.floor() as usize;
+            (index, x - index as f64)
+        }
+        Some(knots) => {
+            if x.is_nan() || x <= knots[0] {
+                return (0, 0.);
+            }
+            if x >= knots[last] {
+                return (last, 0.);
+            }
+            // binary search, knots[index] <= x < knots[index + 1]
+            let index = knots.partition_point(|k| *k <= x) - 1;
+            (
+                index,
+                (x - knots[index]) / (knots[index + 1] - knots[index]),
+            )
+        }
+    }
+}
+
+fn hermite(curve: &[f64], h: &[f64], s: &[f64]) -> Vec<CubicCoefficients> {
+    // Cubic through every pair of points with slopes s, in terms of the position in the segment
+    h.iter()
+        .enumerate()
+        .map(|(i, h)| {
+            let dy = curve[i + 1] - curve[i];
+            CubicCoefficients {
+                a: curve[i],
+                b: s[i] * h,
+                c: 3. * dy - (2. * s[i] + s[i + 1]) * h,
+                d: (s[i] + s[i + 1]) * h - 2. * dy,
+            }
+        })
+        .collect()
+}
+
+fn sample_cubic(curve: &[f64], knots: Option<&[f64]>, coeffs: &[CubicCoefficients], x: f64) -> f64 {
+    let (index, r) = locate(knots, curve.len(), x);
+    if r == 0. {
+        return curve[index];
+    }
+    coeffs[index].eval(r)
+}
+
 pub struct Akima<'a> {
     // Akima interpolator
-    curve: &'a Vec<f64>,
+    curve: &'a [f64],
+    knots: Option<&'a [f64]>,
     coeffs: Vec<CubicCoefficients>,
 }
 
 impl<'a> Akima<'a> {
-    pub fn new(curve: &'a Vec<f64>) -> Akima<'a> {
+    pub fn new(curve: &'a [f64]) -> Akima<'a> {
+        Self::build(curve, None)
+    }
+
+    pub fn with_knots(knots: &'a [f64], curve: &'a [f64]) -> Result<Akima<'a>> {
+        check_knots(knots, curve, true)?;
+        Ok(Self::build(curve, Some(knots)))
+    }
+
+    fn build(curve: &'a [f64], knots: Option<&'a [f64]>) -> Akima<'a> {
         // Calculations based on scipy implementation https://github.com/scipy/scipy/blob/v1.14.0/scipy/interpolate/_cubic.py#L395-L581
         // and Wikipedia article https://en.wikipedia.org/wiki/Akima_spline
         let n = curve.len() - 1;
-        let mut coeffs: Vec<CubicCoefficients> = Vec::with_capacity(n);
+        let h = spacing(knots, curve.len());
         let mut m: Vec<f64> = Vec::with_capacity(n + 4);
         let mut s: Vec<f64> = Vec::with_capacity(n + 1);
 
         for i in 0..n {
-            m.push(curve[i + 1] - curve[i]);
+            m.push((curve[i + 1] - curve[i]) / h[i]);
         }
 
         // Derivatives for first and last points are set to zero, the only modification to Akima spline made here
@@ -53,95 +155,73 @@ impl<'a> Akima<'a> {
             });
         }
 
-        for i in 0..n {
-            coeffs.push(CubicCoefficients {
-                a: curve[i],
-                b: s[i],
-                c: 3. * m[i + 2] - 2. * s[i] - s[i + 1],
-                d: s[i] + s[i + 1] - 2. * m[i + 2],
-            })
+        Self {
+            curve,
+            knots,
+            coeffs: hermite(curve, &h, &s),
         }
-
-        Self { curve, coeffs }
     }
 }
 
 impl Interpolator for Akima<'_> {
     fn sample(&self, x: f64) -> f64 {
-        let x = x.clamp(0., self.curve.len() as f64 - 1.);
-        if x == self.curve.len() as f64 - 1. {
-            return self.curve[self.curve.len() - 1];
-        }
-        if x == 0. {
-            return self.curve[0];
-        }
-        let index = x.floor() as usize;
-        let r = x.fract();
-        let coeff = &self.coeffs[index];
-
-        coeff.a + coeff.b * r + coeff.c * r * r + coeff.d * r * r * r
+        sample_cubic(self.curve, self.knots, &self.coeffs, x)
     }
 }
 
 pub struct CatmullRom<'a> {
     // Catmull-Rom spline
-    curve: &'a Vec<f64>,
+    curve: &'a [f64],
+    knots: Option<&'a [f64]>,
     coeffs: Vec<CubicCoefficients>,
 }
 
 impl<'a> CatmullRom<'a> {
-    pub fn new(curve: &'a Vec<f64>) -> CatmullRom<'a> {
-        // Calculations based on https://www.paulinternet.nl/?page=bicubic
-        let n = curve.len() - 1;
-        let mut coeffs = Vec::with_capacity(n);
-        let mut p = Vec::with_capacity(n + 2);
+    pub fn new(curve: &'a [f64]) -> CatmullRom<'a> {
+        Self::build(curve, None)
+    }
 
-        for x in curve {
-            p.push(*x);
-        }
+    pub fn with_knots(knots: &'a [f64], curve: &'a [f64]) -> Result<CatmullRom<'a>> {
+        check_knots(knots, curve, true)?;
+        Ok(Self::build(curve, Some(knots)))
+    }
 
-        p.insert(0, curve[0]);
-        p.push(curve[curve.len() - 1]);
+    fn build(curve: &'a [f64], knots: Option<&'a [f64]>) -> CatmullRom<'a> {
+        // Calculations based on https://www.paulinternet.nl/?page=bicubic, the slope at each point
+        // is the one between its neighbors. The end points are repeated like the original
+        let n = curve.len() - 1;
+        let h = spacing(knots, curve.len());
+        let s: Vec<f64> = (0..=n)
+            .map(|i| match (i.checked_sub(1), (i < n).then_some(i + 1)) {
+                (Some(a), Some(b)) => (curve[b] - curve[a]) / (h[a] + h[i]),
+                (None, Some(b)) => (curve[b] - curve[i]) / (2. * h[i]),
+                (Some(a), None) => (curve[i] - curve[a]) / (2. * h[a]),
+                (None, None) => 0.,
+            })
+            .collect();
 
-        for i in 0..n {
-            let i = i + 1;
-            coeffs.push(CubicCoefficients {
-                a: -0.5 * p[i - 1] + 1.5 * p[i] - 1.5 * p[i + 1] + 0.5 * p[i + 2],
-                b: p[i - 1] - 2.5 * p[i] + 2. * p[i + 1] - 0.5 * p[i + 2],
-                c: -0.5 * p[i - 1] + 0.5 * p[i + 1],
-                d: p[i],
-            });
+        Self {
+            curve,
+            knots,
+            coeffs: hermite(curve, &h, &s),
         }
-
-        Self { curve, coeffs }
     }
 }
 
 impl Interpolator for CatmullRom<'_> {
     fn sample(&self, x: f64) -> f64 {
-        let x = x.clamp(0., self.curve.len() as f64 - 1.);
-        if x == self.curve.len() as f64 - 1. {
-            return self.curve[self.curve.len() - 1];
-        }
-        if x == 0. {
-            return self.curve[0];
-        }
-        let index = x.floor() as usize;
-        let r = x.fract();
-        let coeff = &self.coeffs[index];
-
-        coeff.a * r * r * r + coeff.b * r * r + coeff.c * r + coeff.d
+        sample_cubic(self.curve, self.knots, &self.coeffs, x)
     }
 }
 
 pub struct Lanczos<'a> {
     // Lanczos interpolation
-    curve: &'a Vec<f64>,
+    curve: &'a [f64],
     q: f64, // Window size...?
 }
 
 impl<'a> Lanczos<'a> {
-    pub fn new(curve: &'a Vec<f64>, q: Option<f64>) -> Lanczos<'a> {
+    pub fn new(curve: &'a [f64], q: Option<f64>) -> Lanczos<'a> {
         Self {
             curve,
             q: q.unwrap_or(3.),
@@ -169,9 +249,7 @@ impl Interpolator for Lanczos<'_> {
         let a = self.q as isize;
         for i in -a..=a {
             let k = index + i;
-            let s = if k < 0 {
-                0.
-            } else if k as usize > self.curve.len() - 1 {
+            let s = if k < 0 || k as usize > self.curve.len() - 1 {
                 0.
             } else {
                 self.curve[k as usize]
@@ -182,16 +260,161 @@ impl Interpolator for Lanczos<'_> {
     }
 }
 
+pub struct Pchip<'a> {
+    // Monotone piecewise cubic Hermite interpolator, never overshoots the points
+    curve: &'a [f64],
+    knots: Option<&'a [f64]>,
+    coeffs: Vec<CubicCoefficients>,
+}
+
+impl<'a> Pchip<'a> {
+    pub fn new(curve: &'a [f64]) -> Pchip<'a> {
+        Self::build(curve, None)
+    }
+
+    pub fn with_knots(knots: &'a [f64], curve: &'a [f64]) -> Result<Pchip<'a>> {
+        check_knots(knots, curve, true)?;
+        Ok(Self::build(curve, Some(knots)))
+    }
+
+    fn build(curve: &'a [f64], knots: Option<&'a [f64]>) -> Pchip<'a> {
+        // Slopes based on scipy implementation https://github.com/scipy/scipy/blob/v1.14.0/scipy/interpolate/_cubic.py#L157-L313
+        // and Fritsch & Butland's weighted harmonic mean
+        let n = curve.len() - 1;
+        let h = spacing(knots, curve.len());
+        let m: Vec<f64> = (0..n).map(|i| (curve[i + 1] - curve[i]) / h[i]).collect();
+        let mut s: Vec<f64> = vec![0.; n + 1];
+
+        if n == 1 {
+            s[0] = m[0];
+            s[1] = m[0];
+        } else if n > 1 {
+            // zero slope at extrema and flat parts so nothing overshoots
+            for i in 1..n {
+                if m[i - 1] * m[i] > 0. {
+                    let w1 = 2. * h[i] + h[i - 1];
+                    let w2 = h[i] + 2. * h[i - 1];
+                    s[i] = (w1 + w2) / (w1 / m[i - 1] + w2 / m[i]);
+                }
+            }
+            s[0] = pchip_edge(h[0], h[1], m[0], m[1]);
+            s[n] = pchip_edge(h[n - 1], h[n - 2], m[n - 1], m[n - 2]);
+        }
+
+        Self {
+            curve,
+            knots,
+            coeffs: hermite(curve, &h, &s),
+        }
+    }
+}
+
+fn pchip_edge(h0: f64, h1: f64, m0: f64, m1: f64) -> f64 {
+    // one-sided three point slope at the ends, limited so it keeps the shape
+    let s = ((2. * h0 + h1) * m0 - h0 * m1) / (h0 + h1);
+    if s.signum() != m0.signum() || m0 == 0. {
+        0.
+    } else if m0.signum() != m1.signum() && s.abs() > 3. * m0.abs() {
+        3. * m0
+    } else {
+        s
+    }
+}
+
+impl Interpolator for Pchip<'_> {
+    fn sample(&self, x: f64) -> f64 {
+        sample_cubic(self.curve, self.knots, &self.coeffs, x)
+    }
+}
+
+pub struct Linear<'a> {
+    // Linear interpolation
+    curve: &'a [f64],
+    knots: Option<&'a [f64]>,
+}
+
+impl<'a> Linear<'a> {
+    pub fn new(curve: &'a [f64]) -> Linear<'a> {
+        Self { curve, knots: None }
+    }
+
+    pub fn with_knots(knots: &'a [f64], curve: &'a [f64]) -> Result<Linear<'a>> {
+        // repeated knots jump straight to the value of the last one
+        check_knots(knots, curve, false)?;
+        Ok(Self {
+            curve,
+            knots: Some(knots),
+        })
+    }
+}
+
+impl Interpolator for Linear<'_> {
+    fn sample(&self, x: f64) -> f64 {
+        let (index, r) = locate(self.knots, self.curve.len(), x);
+        if r == 0. {
+            return self.curve[index];
+        }
+        util::lerp(self.curve[index], self.curve[index + 1], r)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
 pub enum InterpolatorType {
+    #[default]
     Akima,
     CatmullRom,
     Lanczos(Option<f64>),
+    Pchip,
+    Linear,
+}
+
+impl InterpolatorType {
+    pub fn from_value(value: f64) -> Self {
+        match value.round() as i32 {
+            1 => Self::CatmullRom,
+            2 => Self::Lanczos(None),
+            3 => Self::Pchip,
+            4 => Self::Linear,
+            _ => Self::Akima,
+        }
+    }
+}
+
+pub fn make_interpolator(
+    curve: &[f64],
+    interpolator_type: InterpolatorType,
+) -> Box<dyn Interpolator + '_> {
+    // Any interpolator behind the trait
+    match interpolator_type {
+        InterpolatorType::Akima => Box::new(Akima::new(curve)),
+        InterpolatorType::CatmullRom => Box::new(CatmullRom::new(curve)),
+        InterpolatorType::Lanczos(q) => Box::new(Lanczos::new(curve, q)),
+        InterpolatorType::Pchip => Box::new(Pchip::new(curve)),
+        InterpolatorType::Linear => Box::new(Linear::new(curve)),
+    }
+}
+
+pub fn make_interpolator_with_knots<'a>(
+    knots: &'a [f64],
+    curve: &'a [f64],
+    interpolator_type: InterpolatorType,
+) -> Result<Box<dyn Interpolator + 'a>> {
+    // Any interpolator that can take unevenly spaced points, samples are at the knots' x
+    Ok(match interpolator_type {
+        InterpolatorType::Akima => Box::new(Akima::with_knots(knots, curve)?),
+        InterpolatorType::CatmullRom => Box::new(CatmullRom::with_knots(knots, curve)?),
+        InterpolatorType::Lanczos(_) => {
+            return Err(anyhow!("Lanczos interpolation needs evenly spaced points."))
+        }
+        InterpolatorType::Pchip => Box::new(Pchip::with_knots(knots, curve)?),
+        InterpolatorType::Linear => Box::new(Linear::with_knots(knots, curve)?),
+    })
 }
 
 // Tools for interpolating 2D Vecs
 pub fn interpolate_first_axis(
     vec_2d: Vec<Vec<f64>>,
-    points: &Vec<f64>,
+    points: &[f64],
     interpolator_type: InterpolatorType,
 ) -> Vec<Vec<f64>> {
     util::transpose(interpolate_second_axis(
@@ -202,17 +425,13 @@ pub fn interpolate_first_axis(
 }
 
 pub fn interpolate_second_axis(
-    vec_2d: &Vec<Vec<f64>>,
-    points: &Vec<f64>,
+    vec_2d: &[Vec<f64>],
+    points: &[f64],
     interpolator_type: InterpolatorType,
 ) -> Vec<Vec<f64>> {
     let mut interpolated = Vec::with_capacity(vec_2d.len());
     for axis1_vec in vec_2d {
-        let axis1_interpolator: Box<dyn Interpolator> = match interpolator_type {
-            InterpolatorType::Akima => Box::new(Akima::new(axis1_vec)),
-            InterpolatorType::CatmullRom => Box::new(CatmullRom::new(axis1_vec)),
-            InterpolatorType::Lanczos(q) => Box::new(Lanczos::new(axis1_vec, q)),
-        };
+        let axis1_interpolator = make_interpolator(axis1_vec, interpolator_type);
         interpolated.push(axis1_interpolator.sample_with_vec(points))
     }
     interpolated
@@ -220,68 +439,169 @@ pub fn interpolate_second_axis(
 
 #[cfg(test)]
 mod tests {
-    use std::{fs::File, io::Write};
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
 
     use crate::util::transpose;
 
-    use super::{Akima, CatmullRom, Interpolator, Lanczos};
+    use super::{
+        make_interpolator, make_interpolator_with_knots, Akima, CatmullRom, Interpolator,
+        InterpolatorType, Lanczos, Linear, Pchip,
+    };
     const X: [f64; 6] = [1., 2., 4., 2., 3., 2.]; // [0., 0., 0., 0., 0.5, 4., 5., 7.5];
+    const POINTS: [f64; 7] = [0.25, 0.5, 1.5, 2.5, 3.25, 4.5, 4.75];
+
+    fn check(interp: &dyn Interpolator, expected: [f64; 7]) {
+        // passes through the points and matches reference values between them
+        for (i, x) in X.iter().enumerate() {
+            assert!((interp.sample(i as f64) - x).abs() < 1e-12, "point {i}");
+        }
+        for (x, y) in POINTS.iter().zip(expected.iter()) {
+            let sample = interp.sample(*x);
+            assert!((sample - y).abs() < 1e-12, "{x}: {sample} != {y}");
+        }
+        // clamped outside the curve
+        assert_eq!(interp.sample(-1.), X[0]);
+        assert_eq!(interp.sample(10.), X[X.len() - 1]);
+    }
 
     #[test]
     fn test_akima() {
-        let x = X.to_vec();
-        let n = X.len() as i32;
-        let interp = Akima::new(&x);
-
-        let mut csv = File::create("test/akima.csv").expect("Cannot create file");
-        csv.write_all("x,y\n".as_bytes())
-            .expect("Cannot write to file");
-        for i in 0..n - 1 {
-            for j in 0..256 {
-                let i = i as f64 + j as f64 / 256.;
-                let y = interp.sample(i);
-                csv.write_all(format!("{},{}\n", i, y).as_bytes())
-                    .expect("Cannot write to file");
-            }
-        }
+        check(
+            &Akima::new(&X),
+            [1.1, 1.35, 3.025, 3.125, 2.1796875, 2.4375, 2.1328125],
+        );
     }
 
     #[test]
     fn test_catmull_rom() {
-        let x = X.to_vec();
-        let n = X.len() as i32;
-        let interp = CatmullRom::new(&x);
-
-        let mut csv = File::create("test/catmull_rom.csv").expect("Cannot create file");
-        csv.write_all("x,y\n".as_bytes())
-            .expect("Cannot write to file");
-        for i in 0..n - 1 {
-            for j in 0..256 {
-                let i = i as f64 + j as f64 / 256.;
-                let y = interp.sample(i);
-                csv.write_all(format!("{},{}\n", i, y).as_bytes())
-                    .expect("Cannot write to file");
-            }
-        }
+        check(
+            &CatmullRom::new(&X),
+            [1.15625, 1.375, 3.1875, 3.0625, 2.0859375, 2.5625, 2.2265625],
+        );
     }
 
     #[test]
     fn test_lanczos() {
-        let x = X.to_vec();
-        let n = X.len() as i32;
-        let interp = Lanczos::new(&x, None);
-
-        let mut csv = File::create("test/lanczos.csv").expect("Cannot create file");
-        csv.write_all("x,y\n".as_bytes())
-            .expect("Cannot write to file");
-        for i in 0..n - 1 {
-            for j in 0..256 {
-                let i = i as f64 + j as f64 / 256.;
-                let y = interp.sample(i);
-                csv.write_all(format!("{},{}\n", i, y).as_bytes())
-                    .expect("Cannot write to file");
+        // windowed sinc only passes through the points up to rounding
+        let interp = Lanczos::new(&X, None);
+        for (i, x) in X.iter().enumerate() {
+            assert!((interp.sample(i as f64) - x).abs() < 1e-12);
+        }
+        let expected = [
+            1.17399320629518,
+            1.33203582761793,
+            3.31522912877729,
+            3.04503930573106,
+            1.98367901019751,
+            2.86671402252054,
+            2.48454460494738,
+        ];
+        for (x, y) in POINTS.iter().zip(expected.iter()) {
+            assert!((interp.sample(*x) - y).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_pchip() {
+        // harmonic mean slopes inside, three point slopes at the ends
+        check(
+            &Pchip::new(&X),
+            [
+                1.1640625,
+                1.3958333333333333,
+                3.1666666666666667,
+                3.,
+                2.15625,
+                2.75,
+                2.4375,
+            ],
+        );
+
+        // monotone data stays monotone and positive data stays positive
+        let mut rng = StdRng::seed_from_u64(49);
+        for _ in 0..100 {
+            let mut y = 1e-6;
+            let curve: Vec<f64> = (0..20)
+                .map(|_| {
+                    y += rng.gen_range(0..3) as f64 * rng.gen::<f64>();
+                    y
+                })
+                .collect();
+            let interp = Pchip::new(&curve);
+            let samples: Vec<f64> = (0..=190).map(|i| interp.sample(i as f64 / 10.)).collect();
+            assert!(samples.windows(2).all(|w| w[1] >= w[0] - 1e-12));
+            assert!(samples.iter().all(|x| *x > 0.));
+        }
+
+        // two points are a straight line, one point is constant
+        assert_eq!(Pchip::new(&[1., 3.]).sample(0.25), 1.5);
+        assert_eq!(Pchip::new(&[2.]).sample(0.5), 2.);
+    }
+
+    #[test]
+    fn test_linear() {
+        check(&Linear::new(&X), [1.25, 1.5, 3., 3., 2.25, 2.5, 2.25]);
+        assert_eq!(Linear::new(&[2.]).sample(0.5), 2.);
+    }
+
+    #[test]
+    fn test_knots() {
+        let types = [
+            InterpolatorType::Akima,
+            InterpolatorType::CatmullRom,
+            InterpolatorType::Pchip,
+            InterpolatorType::Linear,
+        ];
+
+        // evenly spaced knots anywhere give the same curve as indices
+        let knots: Vec<f64> = (0..X.len()).map(|i| 0.5 * i as f64 - 1.).collect();
+        for interpolator_type in types {
+            let uniform = make_interpolator(&X, interpolator_type);
+            let interp = make_interpolator_with_knots(&knots, &X, interpolator_type).unwrap();
+            for x in (0..=60).map(|i| i as f64 / 10. - 0.5) {
+                let sample = interp.sample(0.5 * x - 1.);
+                assert!((sample - uniform.sample(x)).abs() < 1e-12, "{x}");
+            }
+        }
+
+        // uneven knots pass through the points, found by binary search
+        let knots = [0., 0.1, 0.5, 2., 2.05, 7.];
+        for interpolator_type in types {
+            let interp = make_interpolator_with_knots(&knots, &X, interpolator_type).unwrap();
+            for (k, x) in knots.iter().zip(X.iter()) {
+                assert!((interp.sample(*k) - x).abs() < 1e-12);
             }
+            assert_eq!(interp.sample(-1.), X[0]);
+            assert_eq!(interp.sample(8.), X[X.len() - 1]);
+        }
+        let linear = Linear::with_knots(&knots, &X).unwrap();
+        assert!((linear.sample(1.25) - 3.).abs() < 1e-12);
+        assert!((linear.sample(4.525) - 2.5).abs() < 1e-12);
+
+        // PCHIP keeps a line straight and stays between the points on uneven knots
+        let line: Vec<f64> = knots.iter().map(|k| 2. * k + 1.).collect();
+        let pchip = Pchip::with_knots(&knots, &line).unwrap();
+        for x in (0..=70).map(|i| i as f64 / 10.) {
+            assert!((pchip.sample(x) - (2. * x + 1.)).abs() < 1e-12);
         }
+        let steps = [0., 0., 1., 1., 5., 6.];
+        let pchip = Pchip::with_knots(&knots, &steps).unwrap();
+        let samples: Vec<f64> = (0..=700).map(|i| pchip.sample(i as f64 / 100.)).collect();
+        assert!(samples.windows(2).all(|w| w[1] >= w[0]));
+        assert!(samples[..10].iter().all(|x| *x == 0.));
+
+        // knots have to match the points and go left to right
+        assert!(Akima::with_knots(&knots[..5], &X).is_err());
+        assert!(CatmullRom::with_knots(&[0., 2., 1.], &[0., 1., 2.]).is_err());
+        assert!(Pchip::with_knots(&[0., 1., 1.], &[0., 1., 2.]).is_err());
+        assert!(Linear::with_knots(&[0., f64::NAN], &[0., 1.]).is_err());
+        assert!(Linear::with_knots(&[], &[]).is_err());
+        assert!(make_interpolator_with_knots(&knots, &X, InterpolatorType::Lanczos(None)).is_err());
+
+        // linear takes repeated knots as a jump
+        let linear = Linear::with_knots(&[0., 1., 1., 2.], &[0., 0., 1., 1.]).unwrap();
+        assert_eq!(linear.sample_with_vec(&[0.5, 1., 1.5]), vec![0., 1., 1.]);
     }
 
     #[test]
@@ -299,10 +619,7 @@ mod tests {
 
         let mut interpolated = Vec::with_capacity(32);
         for j in 0..4 {
-            let mut axis_vec = Vec::with_capacity(16);
-            for i in 0..15 {
-                axis_vec.push(test_2d[i][j]);
-            }
+            let axis_vec: Vec<f64> = test_2d.iter().take(15).map(|row| row[j]).collect();
 
             let axis_interp = CatmullRom::new(&axis_vec);
             let mut axis_interpolated = Vec::with_capacity(32);
@@ -318,14 +635,14 @@ mod tests {
             interpolated[0].len()
         );
 
-        for i in 0..16 {
-            let line: Vec<String> = test_2d[i].iter().map(|x| format!("{}", x)).collect();
+        for row in &test_2d {
+            let line: Vec<String> = row.iter().map(|x| format!("{}", x)).collect();
             let line = line.join(", ");
             println!("{}", line);
         }
 
-        for i in 0..32 {
-            let line: Vec<String> = interpolated[i].iter().map(|x| format!("{}", x)).collect();
+        for row in &interpolated {
+            let line: Vec<String> = row.iter().map(|x| format!("{}", x)).collect();
             let line = line.join(", ");
             println!("{}", line);
         }
@@ -6,14 +6,25 @@ mod flags;
 mod interpolator;
 mod parser;
 mod pitchbend;
+mod quantize;
 mod resample;
+mod timing;
+mod tuning;
 mod util;
+mod variation;
+mod vibrato;
+mod vocoder;
 mod world;
 use clap::Parser;
-use parser::ResamplerArgs;
+use parser::{Cli, Command};
 use resample::run;
 
 fn main() {
-    let args = ResamplerArgs::parse(); // Parse arguments using clap
-    run(args).expect("Cannot render note"); // "Resample"
+    let cli = Cli::parse(); // Parse arguments using clap
+    match (cli.command, cli.args) {
+        (Some(Command::Manifest), _) => print!("{}", flags::registry::manifest()),
+        (Some(Command::FlagDocs), _) => print!("{}", flags::registry::flag_docs()),
+        (None, Some(args)) => run(args).expect("Cannot render note"), // "Resample"
+        (None, None) => unreachable!("clap requires a note without a command"),
+    }
 }
@@ -1,17 +1,36 @@
-use crate::util::{pitch_parser, tempo_parser};
-use clap::Parser;
+use crate::util::{self, pitch_parser, tempo_parser};
+use clap::{Parser, Subcommand};
+use std::path::Path;
 
-// Basic resampler args parser
+// Command line, either a note to render or one of the commands
 #[derive(Parser)]
 #[command(name = env!("CARGO_PKG_NAME"))]
 #[command(version = env!("CARGO_PKG_VERSION"))]
 #[command(about = "WORLD-based UTAU resampler on Rust.")]
-#[command()]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    #[command(flatten)]
+    pub args: Option<ResamplerArgs>,
+}
+
+// commands that print files instead of rendering a note
+#[derive(Subcommand)]
+pub enum Command {
+    #[command(about = "Print the OpenUtau resampler manifest made from the flag table.")]
+    Manifest,
+    #[command(about = "Print the flag documentation made from the flag table.")]
+    FlagDocs,
+}
+
+// Basic resampler args parser
+#[derive(Parser)]
 pub struct ResamplerArgs {
     pub in_file: String,
     pub out_file: String,
     #[arg(value_parser = pitch_parser)]
-    pub pitch: i32,
+    pub pitch: f64,
     #[arg(allow_negative_numbers = true)]
     pub velocity: f64,
     #[arg(default_value_t = String::from(""))]
@@ -32,4 +51,86 @@ pub struct ResamplerArgs {
     pub tempo: f64,
     #[arg(default_value_t = String::from("AA"))]
     pub pitchbend: String,
+    // fail on unknown or malformed flags instead of skipping them
+    #[arg(long)]
+    pub strict: bool,
+    // Scala scale and keymap, straycat-rs.scl and straycat-rs.kbm next to the resampler otherwise
+    #[arg(long)]
+    pub scl: Option<String>,
+    #[arg(long)]
+    pub kbm: Option<String>,
+}
+
+impl ResamplerArgs {
+    pub fn seed(&self) -> u64 {
+        // Random seed from everything that changes the render. Only the file name of the sample
+        // is used so the same project renders the same on another computer
+        let sample = Path::new(&self.in_file)
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let key = format!(
+            "{sample}|{}|{}|{}|{}|{}|{}|{}|{}|{}|{}|{}",
+            self.pitch,
+            self.velocity,
+            self.flags,
+            self.offset,
+            self.length,
+            self.consonant,
+            self.cutoff,
+            self.volume,
+            self.modulation,
+            self.tempo,
+            self.pitchbend
+        );
+        util::hash(key.as_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Cli, Command, ResamplerArgs};
+    use clap::Parser;
+
+    fn args(in_file: &str, out_file: &str, flags: &str) -> ResamplerArgs {
+        ResamplerArgs::parse_from([
+            "straycat-rs",
+            in_file,
+            out_file,
+            "C4",
+            "100",
+            flags,
+            "50",
+            "3000",
+            "150",
+            "-1200",
+            "100",
+            "0",
+            "!120",
+            "AA",
+        ])
+    }
+
+    #[test]
+    fn test_seed() {
+        // same note gives the same seed wherever the files are
+        let seed = args("/voice/a.wav", "/tmp/1.wav", "g5").seed();
+        assert_eq!(seed, args("/home/voice/a.wav", "/tmp/2.wav", "g5").seed());
+        assert_ne!(seed, args("/voice/a.wav", "/tmp/1.wav", "g6").seed());
+        assert_ne!(seed, args("/voice/i.wav", "/tmp/1.wav", "g5").seed());
+    }
+
+    #[test]
+    fn test_commands() {
+        // commands only take the place of a whole note, never of the sample path
+        let cli = Cli::parse_from(["straycat-rs", "manifest"]);
+        assert!(matches!(cli.command, Some(Command::Manifest)) && cli.args.is_none());
+        let cli = Cli::parse_from(["straycat-rs", "flag-docs"]);
+        assert!(matches!(cli.command, Some(Command::FlagDocs)));
+        assert!(Cli::try_parse_from(["straycat-rs", "manifest", "out.wav", "C4", "100"]).is_err());
+
+        let cli = Cli::parse_from(["straycat-rs", "./manifest", "out.wav", "C4", "100"]);
+        assert!(cli.command.is_none());
+        assert_eq!(cli.args.unwrap().in_file, "./manifest");
+    }
 }
@@ -0,0 +1,105 @@
+use anyhow::Result;
+use std::fmt;
+
+use crate::interpolator::interp::{make_interpolator, InterpolatorType};
+use crate::pitchbend::encoder::cents_to_pitch_string;
+use crate::pitchbend::parser::pitch_string_to_cents;
+
+// Pitchbend with the timing needed to place it in the render
+#[derive(Debug, Clone, PartialEq)]
+pub struct PitchCurve {
+    // one point per tick, in cents
+    pub cents: Vec<f64>,
+    // ticks per second
+    pub rate: f64,
+    // time of the first tick in seconds from the start of the render
+    pub offset: f64,
+}
+
+impl PitchCurve {
+    pub fn new(cents: Vec<f64>, rate: f64, offset: f64) -> Self {
+        Self {
+            cents,
+            rate,
+            offset,
+        }
+    }
+
+    pub fn tempo_rate(tempo: f64) -> f64 {
+        // UTAU sends a point every 5 ticks of a 480 tick beat
+        8. * tempo / 5.
+    }
+
+    pub fn from_pitch_string<S: AsRef<str>>(pitch_string: S, tempo: f64) -> Result<Self> {
+        // Decode a UTAU pitchbend argument, the first point is at the start of the render
+        let cents = pitch_string_to_cents(pitch_string)?;
+        Ok(Self::new(cents, Self::tempo_rate(tempo), 0.))
+    }
+
+    pub fn midi(&self) -> Vec<f64> {
+        // Points in semitones
+        self.cents.iter().map(|x| x / 100.).collect()
+    }
+
+    pub fn sample(&self, t: &[f64], interpolator_type: InterpolatorType) -> Vec<f64> {
+        // Pitchbend in semitones at every timestamp in seconds
+        let midi = self.midi();
+        let interp = make_interpolator(&midi, interpolator_type);
+        let ticks: Vec<f64> = t.iter().map(|t| (t - self.offset) * self.rate).collect();
+        interp.sample_with_vec(&ticks)
+    }
+}
+
+impl fmt::Display for PitchCurve {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Encode as a UTAU pitchbend argument. The rate and offset are not part of the string
+        write!(f, "{}", cents_to_pitch_string(&self.cents))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PitchCurve;
+    use crate::interpolator::interp::InterpolatorType;
+
+    #[test]
+    fn test_pitch_curve() {
+        // a cent curve becomes a pitch string and comes back with the tempo's tick rate
+        let cents: Vec<f64> = (0..100)
+            .map(|i| (300. * (i as f64 / 10.).sin()).round())
+            .collect();
+        let curve = PitchCurve::new(cents, PitchCurve::tempo_rate(120.), 0.);
+        let decoded = PitchCurve::from_pitch_string(curve.to_string(), 120.).unwrap();
+        assert_eq!(decoded, curve);
+        assert_eq!(decoded.rate, 192.);
+
+        // whole ticks sample the points, the offset shifts the curve
+        let t: Vec<f64> = (0..5).map(|i| i as f64 / 192.).collect();
+        let sampled = curve.sample(&t, InterpolatorType::Akima);
+        for (s, c) in sampled.iter().zip(curve.cents.iter()) {
+            assert!((s - c / 100.).abs() < 1e-9);
+        }
+        let shifted = PitchCurve::new(curve.cents.clone(), 192., 1. / 192.);
+        let t_shifted: Vec<f64> = t.iter().map(|t| t + 1. / 192.).collect();
+        assert_eq!(shifted.sample(&t_shifted, InterpolatorType::Akima), sampled);
+
+        // the interpolator only changes the curve between the points
+        let step = PitchCurve::new(vec![0., 0., 100., 200., 500., 500.], 1., 0.);
+        let t: Vec<f64> = (0..=50).map(|i| i as f64 / 10.).collect();
+        let akima = step.sample(&t, InterpolatorType::Akima);
+        let catmull_rom = step.sample(&t, InterpolatorType::CatmullRom);
+        assert_ne!(akima, catmull_rom);
+        for i in 0..6 {
+            assert!((akima[10 * i] - catmull_rom[10 * i]).abs() < 1e-9);
+        }
+        // an accelerating glide dips before it starts with Akima but not with PCHIP or linear
+        let overshoot = |interpolator_type| {
+            step.sample(&t, interpolator_type)
+                .iter()
+                .any(|x| *x < 0. || *x > 5.)
+        };
+        assert!(overshoot(InterpolatorType::Akima));
+        assert!(!overshoot(InterpolatorType::Pchip));
+        assert!(!overshoot(InterpolatorType::Linear));
+    }
+}
@@ -0,0 +1,68 @@
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn from_int12(x: i16) -> [char; 2] {
+    // weird signed 12-bit integer to two Base64 chars
+    let uint12 = (x as i32).rem_euclid(4096) as usize;
+    [BASE64[uint12 >> 6] as char, BASE64[uint12 & 63] as char]
+}
+
+pub fn cents_to_pitch_string(pitchbend: &[f64]) -> String {
+    // Pitchbend in cents to a UTAU pitchbend argument. Values are rounded and clamped to what
+    // 12 bits can hold, repeats are run-length encoded as #n#
+    let points: Vec<i16> = pitchbend
+        .iter()
+        .map(|x| x.round().clamp(-2048., 2047.) as i16)
+        .collect();
+
+    let mut pitch_string = String::new();
+    let mut i = 0;
+    while i < points.len() {
+        let run = points[i..].iter().take_while(|p| **p == points[i]).count();
+        pitch_string.extend(from_int12(points[i]));
+        // #n# is shorter than writing the point again from two repeats on
+        match run - 1 {
+            0 => {}
+            1 => pitch_string.extend(from_int12(points[i])),
+            repeats => pitch_string += &format!("#{repeats}#"),
+        }
+        i += run;
+    }
+    pitch_string
+}
+
+#[cfg(test)]
+mod tests {
+    use super::cents_to_pitch_string;
+    use crate::pitchbend::parser::pitch_string_to_cents;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    #[test]
+    fn test_encoder() {
+        assert_eq!(cents_to_pitch_string(&[]), "");
+        assert_eq!(cents_to_pitch_string(&[0., 1., -1.]), "AAAB//");
+        assert_eq!(cents_to_pitch_string(&[2047., -2048., 10000.]), "f/gAf/");
+        assert_eq!(cents_to_pitch_string(&[0., 0.]), "AAAA");
+        assert_eq!(cents_to_pitch_string(&[0., 0., 0., 50.]), "AA#2#Ay");
+    }
+
+    #[test]
+    fn test_round_trip() {
+        // random curves with runs of repeated points survive encoding and decoding
+        let mut rng = StdRng::seed_from_u64(47);
+        for _ in 0..500 {
+            let mut cents = Vec::new();
+            while cents.len() < 300 {
+                let value = rng.gen_range(-2048..=2047);
+                let run = match rng.gen_range(0..4) {
+                    0 => rng.gen_range(2..40),
+                    _ => 1,
+                };
+                cents.resize(cents.len() + run, value as f64);
+            }
+            let pitch_string = cents_to_pitch_string(&cents);
+            let decoded = pitch_string_to_cents(&pitch_string).expect("Cannot decode");
+            assert_eq!(decoded, cents, "{pitch_string}");
+        }
+    }
+}
@@ -1 +1,3 @@
+pub mod curve;
+pub mod encoder;
 pub mod parser;
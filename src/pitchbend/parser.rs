@@ -46,15 +46,15 @@ fn to_int12_stream<S: AsRef<str>>(b64: S) -> Result<Vec<i16>> {
     Ok(stream)
 }
 
-pub fn pitch_string_to_midi<S: AsRef<str>>(pitch_string: S) -> Result<Vec<f64>> {
-    // UTAU pitchbend argument to MIDI
+pub fn pitch_string_to_cents<S: AsRef<str>>(pitch_string: S) -> Result<Vec<f64>> {
+    // UTAU pitchbend argument to cents
     let pitch_string = pitch_string.as_ref();
     let mut pitchbend: Vec<i16> = Vec::new();
 
     let pitch_rle: Vec<&str> = pitch_string.split("#").collect();
     for chunk in pitch_rle.chunks(2) {
         let mut stream = to_int12_stream(chunk[0])?;
-        let last_point = if stream.len() > 0 {
+        let last_point = if !stream.is_empty() {
             let temp = stream[stream.len() - 1];
             pitchbend.append(&mut stream);
             Some(temp)
@@ -71,20 +71,29 @@ pub fn pitch_string_to_midi<S: AsRef<str>>(pitch_string: S) -> Result<Vec<f64>>
         }
     }
 
-    let pitchbend: Vec<f64> = pitchbend.into_iter().map(|x| x as f64 / 100.).collect();
-    Ok(pitchbend)
+    Ok(pitchbend.into_iter().map(|x| x as f64).collect())
 }
 
 #[cfg(test)]
 mod tests {
-    use super::pitch_string_to_midi;
+    use super::pitch_string_to_cents;
+    use crate::pitchbend::encoder::cents_to_pitch_string;
 
     #[test]
     fn test_pitch_string() {
         let test = "B7CPCVCVCTCQCNCICDB+B5B0BvBrBnBlBk#14#BjBF/++Y8k615d4p4f4l4y5G5f596e7B7l8H8n9D9Z9q9092919y9t9n9f9Y9Q9I9C898584858/9L9b9v+G+f+4/Q/m/5AIATAY#2#AWAUARAOALAHAFACABAA";
-        let pitchbend = pitch_string_to_midi(&test).expect("Failed to parse");
-        for p in &pitchbend {
-            println!("{}", p);
-        }
+        let pitchbend = pitch_string_to_cents(test).expect("Failed to parse");
+        assert_eq!(pitchbend.len(), 93);
+        assert_eq!(pitchbend[..3], [123., 143., 149.]);
+        // #14# repeats the point before it 14 more times
+        assert!(pitchbend[16..31].iter().all(|p| *p == 100.));
+        assert_eq!(pitchbend[31], 99.);
+        assert_eq!(pitchbend[80..84], [24., 24., 24., 22.]);
+        assert_eq!(pitchbend[pitchbend.len() - 1], 0.);
+        assert_eq!(cents_to_pitch_string(&pitchbend), test);
+
+        assert!(pitch_string_to_cents("AAA").is_err());
+        assert!(pitch_string_to_cents("A*").is_err());
+        assert!(pitch_string_to_cents("AA#x#").is_err());
     }
 }
@@ -0,0 +1,192 @@
+use crate::tuning::Tuning;
+use crate::{consts, util};
+
+// how many octaves of keys the scale may span, ends the search for scales that don't rise
+const MAX_OCTAVES: usize = 128;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Scale {
+    #[default]
+    Chromatic,
+    Major,
+    Minor,
+}
+
+impl Scale {
+    pub fn from_value(value: f64) -> Self {
+        match value.round() as i32 {
+            1 => Self::Major,
+            2 => Self::Minor,
+            _ => Self::Chromatic,
+        }
+    }
+
+    fn degrees(&self) -> &'static [f64] {
+        // semitones above the root
+        match self {
+            Self::Chromatic => &[0., 1., 2., 3., 4., 5., 6., 7., 8., 9., 10., 11.],
+            Self::Major => &[0., 2., 4., 5., 7., 9., 11.],
+            Self::Minor => &[0., 2., 3., 5., 7., 8., 10.],
+        }
+    }
+
+    pub fn pitches(&self, root: f64, tuning: &Tuning, low: f64, high: f64) -> Vec<f64> {
+        // Every note of the scale from below low to above high. Notes are keys of the tuning,
+        // so the scale follows the active tuning, returned as sorted 12-TET MIDI numbers
+        let pitch = |key: f64| util::hz_to_midi(tuning.midi_to_hz(key));
+        let mut octave = ((low - root) / 12.).floor();
+        for _ in 0..MAX_OCTAVES {
+            if pitch(root + 12. * octave) <= low {
+                break;
+            }
+            octave -= 1.;
+        }
+        let mut pitches = Vec::new();
+        for _ in 0..MAX_OCTAVES {
+            pitches.extend(
+                self.degrees()
+                    .iter()
+                    .map(|d| pitch(root + 12. * octave + d)),
+            );
+            if pitches[pitches.len() - 1] >= high {
+                break;
+            }
+            octave += 1.;
+        }
+        pitches.sort_by(|a, b| a.total_cmp(b));
+        pitches
+    }
+}
+
+fn nearest(pitches: &[f64], midi: f64) -> f64 {
+    // closest note of the sorted scale pitches
+    let i = pitches.partition_point(|p| *p < midi);
+    let below = pitches[i.saturating_sub(1)];
+    let above = pitches[i.min(pitches.len() - 1)];
+    if midi - below <= above - midi {
+        below
+    } else {
+        above
+    }
+}
+
+pub fn quantize(
+    midi: &[f64],
+    vuv: &[bool],
+    strength: f64,
+    speed: f64,
+    scale: Scale,
+    root: f64,
+    tuning: &Tuning,
+) -> Vec<f64> {
+    // Pull voiced frames towards the scale. The correction is smoothed with the retune speed in
+    // ms, so slow speeds only fix drift and let vibrato through while 0 snaps instantly
+    let (low, high) = midi
+        .iter()
+        .zip(vuv.iter())
+        .filter(|(_, voiced)| **voiced)
+        .fold((f64::MAX, f64::MIN), |(low, high), (m, _)| {
+            (low.min(*m), high.max(*m))
+        });
+    if low > high {
+        return midi.to_vec();
+    }
+    let pitches = scale.pitches(root, tuning, low, high);
+    let rate = if speed > 0. {
+        1. - (-consts::FRAME_PERIOD / speed).exp()
+    } else {
+        1.
+    };
+    let mut correction: Option<f64> = None;
+    midi.iter()
+        .zip(vuv.iter())
+        .map(|(m, voiced)| {
+            if !*voiced {
+                // start over on the next voiced part
+                correction = None;
+                return *m;
+            }
+            let target = nearest(&pitches, *m) - m;
+            let c = match correction {
+                Some(c) => c + rate * (target - c),
+                None => target,
+            };
+            correction = Some(c);
+            m + strength * c
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{nearest, quantize, Scale};
+    use crate::tuning::Tuning;
+
+    #[test]
+    fn test_nearest() {
+        let near = |scale: Scale, midi: f64, root: f64, tuning: &Tuning| {
+            nearest(&scale.pitches(root, tuning, midi, midi), midi)
+        };
+        let tet = Tuning::default();
+        let cases = [
+            (Scale::Chromatic, 60.3, 0., 60.),
+            (Scale::Chromatic, 60.7, 0., 61.),
+            // C# is not in C major, D major has it
+            (Scale::Major, 61.2, 0., 62.),
+            (Scale::Major, 61.2, 2., 61.),
+            (Scale::Minor, 63.4, 0., 63.),
+            // wraps around the octave
+            (Scale::Major, 71.9, 0., 72.),
+            (Scale::Minor, 58.8, 0., 58.),
+            (Scale::Major, -0.6, 0., -1.),
+        ];
+        for (scale, midi, root, expected) in cases {
+            let pitch = near(scale, midi, root, &tet);
+            assert!((pitch - expected).abs() < 1e-9, "{midi}: {pitch}");
+        }
+
+        // 24-EDO has a key every 50 cents, A4 stays in place
+        let edo = Tuning::edo(24.);
+        assert!((near(Scale::Chromatic, 69.3, 0., &edo) - 69.5).abs() < 1e-9);
+        assert!((near(Scale::Chromatic, 68.8, 0., &edo) - 69.).abs() < 1e-9);
+        // C major keys of 24-EDO, C4 and D4 are 100 cents apart
+        assert!((near(Scale::Major, 64.6, 0., &edo) - 64.5).abs() < 1e-9);
+        assert!((near(Scale::Major, 65.6, 0., &edo) - 65.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_quantize() {
+        // slow drift with vibrato on top
+        let midi: Vec<f64> = (0..400)
+            .map(|i| {
+                let t = i as f64 * 0.005;
+                60.1 + 0.05 * t + 0.2 * (std::f64::consts::TAU * 6. * t).sin()
+            })
+            .collect();
+        let vuv = vec![true; midi.len()];
+        let tet = Tuning::default();
+
+        // hard tune
+        let hard = quantize(&midi, &vuv, 1., 0., Scale::Chromatic, 0., &tet);
+        assert!(hard.iter().all(|m| (m - 60.).abs() < 1e-9));
+
+        // half strength goes half way
+        let half = quantize(&midi, &vuv, 0.5, 0., Scale::Chromatic, 0., &tet);
+        assert!((half[0] - 60.05).abs() < 1e-9);
+
+        // slow retune fixes the offset but keeps most of the vibrato
+        let slow = quantize(&midi, &vuv, 1., 200., Scale::Chromatic, 0., &tet);
+        let tail = &slow[300..];
+        let mean = tail.iter().sum::<f64>() / tail.len() as f64;
+        let depth = tail.iter().fold(0., |d: f64, m| d.max((m - mean).abs()));
+        assert!((mean - 60.).abs() < 0.1, "{mean}");
+        assert!(depth > 0.15, "{depth}");
+
+        // unvoiced frames are left alone
+        let mut vuv = vuv;
+        vuv[10] = false;
+        let tuned = quantize(&midi, &vuv, 1., 0., Scale::Chromatic, 0., &tet);
+        assert_eq!(tuned[10], midi[10]);
+        assert!((tuned[11] - 60.).abs() < 1e-9);
+    }
+}
@@ -1,22 +1,62 @@
 use crate::audio::post_process::{peak_compression, peak_normalization};
 use crate::audio::read_write::{read_audio, write_audio};
+use crate::audio::stretch;
+use crate::flags::curves::{read_curves, render_curve, to_curve_path, ExpressionCurves};
 use crate::flags::parser::Flags;
+use crate::flags::presets::Presets;
 use crate::interpolator::interp::{self, Interpolator};
 use crate::parser::ResamplerArgs;
+use crate::pitchbend::curve::PitchCurve;
+use crate::timing::{self, StretchMode, TimeMap};
+use crate::tuning::Tuning;
 use crate::util::{self, smoothstep};
-use crate::world::features::{generate_features, read_features, to_feature_path};
-use crate::world::synthesis::{synthesize_aperiodic, synthesize_harmonic};
-use crate::{consts, filter, pitchbend};
+use crate::variation::{self, Variation};
+use crate::vibrato::Vibrato;
+use crate::vocoder::backend::{Analyzer, Synthesizer};
+use crate::vocoder::hnm::HarmonicNoise;
+use crate::world::features::{decode_features, generate_features, read_features, to_feature_path};
+use crate::world::vocoder::World;
+use crate::{consts, filter, quantize};
 use anyhow::Result;
-use biquad::{Biquad, DirectForm1, DirectForm2Transposed, Q_BUTTERWORTH_F64};
-use rand::thread_rng;
-use rand_distr::{Distribution, Normal};
+use biquad::{DirectForm2Transposed, Q_BUTTERWORTH_F64};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use rand_distr::{Distribution, StandardNormal};
 use std::path::Path;
 
+const LOOP_CROSSFADE: f64 = 0.1; // seconds
+
 pub fn run(args: ResamplerArgs) -> Result<()> {
     // Main resampler function
+    // parse flags
+    let presets = Presets::load()?;
+    let flags = if args.strict {
+        Flags::parse_strict(&args.flags, &presets)?
+    } else {
+        Flags::parse_lenient(&args.flags, &presets)
+    };
+
+    // seed for everything random, the same note always renders the same
+    let seed = flags.seed.unwrap_or_else(|| args.seed());
+
+    // pick synthesis backend
+    if flags.harmonic_noise {
+        println!("Using harmonic plus noise synthesis.");
+        run_with(args, flags, seed, &World, &HarmonicNoise::new(seed))
+    } else {
+        run_with(args, flags, seed, &World, &World)
+    }
+}
+
+pub fn run_with<A: Analyzer, S: Synthesizer>(
+    args: ResamplerArgs,
+    flags: Flags,
+    seed: u64,
+    analyzer: &A,
+    synthesizer: &S,
+) -> Result<()> {
+    // Resampler pipeline with any vocoder backend
     let null_out = &args.out_file == "nul"; // null file from Initialize freq. map args
-    let flags: Flags = args.flags.replace("/", "").parse()?; // parse flags
 
     // input file and feature file
     let in_file = Path::new(&args.in_file);
@@ -30,17 +70,25 @@ pub fn run(args: ResamplerArgs) -> Result<()> {
             threshold
         );
         let audio = read_audio(&args.in_file)?;
-        generate_features(&args.in_file, audio, Some(threshold))?;
+        generate_features(analyzer, &args.in_file, audio, Some(threshold))?;
     }
 
     // generate feature file if it doesn't exist
     let features = if !feature_path.exists() {
         println!("Generating features.");
         let audio = read_audio(&args.in_file)?;
-        generate_features(&args.in_file, audio, None)?
+        generate_features(analyzer, &args.in_file, audio, None)?
     } else {
         println!("Reading features.");
-        read_features(&feature_path)?
+        match read_features(&feature_path) {
+            Ok(features) => features,
+            Err(_) => {
+                // feature files from older versions can't be read
+                println!("Cannot read features. Regenerating.");
+                let audio = read_audio(&args.in_file)?;
+                generate_features(analyzer, &args.in_file, audio, None)?
+            }
+        }
     };
 
     // skip null output
@@ -50,25 +98,22 @@ pub fn run(args: ResamplerArgs) -> Result<()> {
     }
 
     let out_file = Path::new(&args.out_file); // output file
+    let curve_path = to_curve_path(out_file);
+    let curves = if curve_path.exists() {
+        println!("Reading expression curves.");
+        read_curves(&curve_path)?
+    } else {
+        ExpressionCurves::default()
+    };
     let velocity = (1. - args.velocity / 100.).exp2(); // velocity as stretch
     let volume = args.volume / 100.; // volume
     let modulation = args.modulation / 100.; // mod
 
-    println!("Decoding WORLD features.");
+    println!("Decoding features.");
 
     let feature_length = features.f0.len();
     let feature_dim = (consts::FFT_SIZE / 2 + 1) as usize;
-    let sp = rsworld::decode_spectral_envelope(
-        &features.mgc,
-        feature_length as i32,
-        consts::SAMPLE_RATE as i32,
-        consts::FFT_SIZE,
-    );
-    let ap = rsworld::decode_aperiodicity(
-        &features.bap,
-        feature_length as i32,
-        consts::SAMPLE_RATE as i32,
-    );
+    let (sp, ap) = decode_features(&features);
     let vuv: Vec<bool> = features.f0.iter().map(|f0| *f0 != 0.).collect();
     let f0_off: Vec<f64> = features
         .f0
@@ -100,43 +145,105 @@ pub fn run(args: ResamplerArgs) -> Result<()> {
 
     println!("Preparing interpolation.");
 
-    let t_consonant = util::linspace(
-        start,
-        consonant,
-        (velocity * args.consonant / consts::FRAME_PERIOD) as usize,
-        false,
+    // output is exactly the stretched consonant plus the requested length, the render gets
+    // enough frames to cover it and is trimmed at the end
+    let out_length = timing::output_samples(velocity * args.consonant + args.length);
+    let consonant_frames = (velocity * args.consonant / consts::FRAME_PERIOD).round() as usize;
+    let energy = timing::frame_energy(&sp);
+    let t_consonant = timing::consonant_map(
+        flags.consonant_mode,
+        start * fps,
+        consonant * fps,
+        consonant_frames,
+        &energy,
+        &vuv,
     );
 
     let length_req = args.length / 1000.;
     let stretch_length = end - consonant;
+    let stretch_frames = timing::frames_for_samples(out_length).saturating_sub(consonant_frames);
     let t_stretch = if stretch_length > length_req {
         let con_idx = (consonant * fps) as usize;
-        let len_idx = (length_req * fps) as usize;
-        t_features[con_idx..(con_idx + len_idx).min(feature_length - 1)].to_vec()
+        TimeMap::new(
+            t_features[con_idx..(con_idx + stretch_frames).min(feature_length - 1)]
+                .iter()
+                .map(|x| x * fps)
+                .collect(),
+        )
     } else {
-        util::linspace(consonant, end, (length_req * fps) as usize, true)
+        // only stretch the steadiest part of the sample, onset and release stay natural
+        let region = timing::stable_region(&features.instability, consonant * fps, end * fps);
+        match flags.stretch_mode {
+            StretchMode::Loop => println!("Looping the stretch region."),
+            StretchMode::PingPong => println!("Ping-ponging the stretch region."),
+            StretchMode::Stretch => (),
+        }
+        timing::stretch_map(
+            flags.stretch_mode,
+            consonant * fps,
+            region,
+            end * fps,
+            stretch_frames,
+            LOOP_CROSSFADE * fps,
+        )
     };
     let consonant = velocity * args.consonant / 1000.; // timestamp of consonant in the render
 
-    let t_render: Vec<f64> = t_consonant
-        .into_iter()
-        .chain(t_stretch.into_iter())
-        .map(|x| x * fps)
-        .collect();
-    let render_length = t_render.len();
+    let mut time_map = t_consonant;
+    time_map.append(t_stretch);
+    let t_render = &time_map.t;
+    let render_length = time_map.len();
 
     println!("Interpolating WORLD features.");
     let f0_off_interp = interp::Akima::new(&f0_off);
 
-    let f0_off_render = f0_off_interp.sample_with_vec(&t_render);
-    let vuv_render: Vec<bool> = t_render
+    let mut f0_off_render = f0_off_interp.sample_with_vec(t_render);
+    let mut vuv_render: Vec<bool> = t_render
         .iter()
         .map(|i| vuv[(*i as usize).clamp(0, feature_length - 1)])
         .collect();
-    let mut sp_render =
-        interp::interpolate_first_axis(sp, &t_render, interp::InterpolatorType::Akima);
-    let mut ap_render =
-        interp::interpolate_first_axis(ap, &t_render, interp::InterpolatorType::Akima);
+    if time_map.is_blended() {
+        // crossfade pitch at loop seams, only glide when both sides are voiced
+        time_map
+            .t_blend
+            .iter()
+            .zip(time_map.blend.iter())
+            .zip(f0_off_render.iter_mut().zip(vuv_render.iter_mut()))
+            .filter(|((_, blend), _)| **blend > 0.)
+            .for_each(|((t, blend), (f0_off, voiced))| {
+                let voiced_blend = vuv[(*t as usize).clamp(0, feature_length - 1)];
+                let f0_off_blend = f0_off_interp.sample(*t);
+                if *voiced && voiced_blend {
+                    *f0_off = util::lerp(*f0_off, f0_off_blend, *blend);
+                } else if *blend > 0.5 {
+                    *f0_off = f0_off_blend;
+                    *voiced = voiced_blend;
+                }
+            });
+    }
+    // slow random drift for frames that are stretched past the sample
+    let variation = if flags.variation != 0. {
+        println!("Adding variation to the stretched region.");
+        let stats = variation::estimate(
+            &f0_off,
+            &vuv,
+            &sp,
+            &ap,
+            ((start + args.consonant / 1000.) * fps) as usize..(end * fps) as usize,
+        );
+        let weight = variation::stretch_weight(t_render, (consonant * fps) as usize);
+        let variation = Variation::new(
+            &stats,
+            &weight,
+            flags.variation / 100.,
+            seed ^ consts::VARIATION_SEED,
+        );
+        variation.apply_f0(&mut f0_off_render, &vuv_render);
+        Some(variation)
+    } else {
+        None
+    };
+
     let t_sec: Vec<f64> = util::arange(render_length as i32)
         .iter()
         .map(|x| x / fps)
@@ -147,30 +254,123 @@ pub fn run(args: ResamplerArgs) -> Result<()> {
     if flags.pitch_offset != 0. {
         println!("Applying pitch offset.");
     }
-    let pitch = pitchbend::parser::pitch_string_to_midi(args.pitchbend)?;
-    let pps = 8. * args.tempo / 5.; // pitchbend points per second
-    let pitch_interp = interp::Akima::new(&pitch);
-    let t_pitch: Vec<f64> = t_sec.iter().map(|x| x * pps).collect();
-    let pitch_render = pitch_interp.sample_with_vec(&t_pitch);
+    let tuning = Tuning::load(
+        args.scl.as_deref(),
+        args.kbm.as_deref(),
+        flags.tuning_reference,
+        flags.tuning_edo,
+    )?;
+    if !tuning.is_standard() {
+        println!("Using custom tuning.");
+    }
+    let pitch_curve = PitchCurve::from_pitch_string(&args.pitchbend, args.tempo)?;
+    let pitch_render = pitch_curve.sample(&t_sec, flags.pitch_interpolation);
 
-    let mut f0_render: Vec<f64> = pitch_render
+    // only the note and pitchbend go through the tuning, everything on top of them is in
+    // cents of 12-TET so the offset flags and modulation sound the same in every tuning
+    let mut midi_render: Vec<f64> = pitch_render
         .iter()
-        .zip(f0_off_render.into_iter().zip(vuv_render.iter()))
-        .map(|(pitch, (f0_off, vuv))| {
-            if *vuv {
-                util::midi_to_hz(
-                    *pitch + args.pitch as f64 + flags.pitch_offset / 100. + f0_off * modulation,
-                )
-            } else {
-                0.
-            }
+        .zip(f0_off_render.iter())
+        .map(|(pitch, f0_off)| {
+            util::hz_to_midi(tuning.midi_to_hz(args.pitch + pitch))
+                + flags.pitch_offset / 100.
+                + f0_off * modulation
         })
         .collect();
+    if flags.quantize != 0. {
+        println!("Quantizing pitch.");
+        midi_render = quantize::quantize(
+            &midi_render,
+            &vuv_render,
+            flags.quantize / 100.,
+            flags.quantize_speed,
+            flags.quantize_scale,
+            flags.quantize_root,
+            &tuning,
+        );
+    }
+    let vibrato = if flags.vibrato_depth != 0. {
+        println!("Adding vibrato.");
+        let vibrato = Vibrato {
+            rate: flags.vibrato_rate,
+            depth: flags.vibrato_depth,
+            delay: flags.vibrato_delay / 1000.,
+            fade_in: flags.vibrato_fade / 1000.,
+            drift: flags.vibrato_drift / 100.,
+        }
+        .render(&t_sec, consonant, seed);
+        midi_render
+            .iter_mut()
+            .zip(vibrato.iter())
+            .for_each(|(midi, v)| *midi += v);
+        Some(vibrato)
+    } else {
+        None
+    };
+    let mut f0_render: Vec<f64> = midi_render
+        .iter()
+        .zip(vuv_render.iter())
+        .map(|(midi, vuv)| if *vuv { util::midi_to_hz(*midi) } else { 0. })
+        .collect();
+
+    // skip resynthesis if the render would sound the same as the sample
+    let pitch_deviation = f0_render
+        .iter()
+        .zip(f0_off_render.iter())
+        .filter(|(f0, _)| **f0 > 0.)
+        .map(|(f0, f0_off)| (12. * (f0 / features.base_f0).log2() - f0_off).abs())
+        .fold(0., f64::max);
+    let time_deviation = t_render
+        .iter()
+        .enumerate()
+        .map(|(i, t)| (t - t_render[0] - i as f64).abs())
+        .fold(0., f64::max);
+    if !flags.needs_resynthesis()
+        && curves.is_empty()
+        && pitch_deviation < consts::BYPASS_PITCH_TOLERANCE
+        && time_deviation < consts::BYPASS_TIME_TOLERANCE
+    {
+        println!("Render matches the sample. Skipping resynthesis.");
+        let audio = read_audio(&args.in_file)?;
+        let syn = trim_original(&audio, t_render[0] / fps, out_length, volume);
+        return write_render(out_file, syn, out_length, &flags);
+    }
 
-    if flags.gender != 0. {
+    let (mut sp_render, mut ap_render) = if time_map.is_blended() {
+        // crossfade spectral features at loop seams
+        let mut sp_render =
+            interp::interpolate_first_axis(sp.clone(), t_render, interp::InterpolatorType::Akima);
+        let mut ap_render =
+            interp::interpolate_first_axis(ap.clone(), t_render, interp::InterpolatorType::Akima);
+        let sp_blend =
+            interp::interpolate_first_axis(sp, &time_map.t_blend, interp::InterpolatorType::Akima);
+        let ap_blend =
+            interp::interpolate_first_axis(ap, &time_map.t_blend, interp::InterpolatorType::Akima);
+        blend_frames(&mut sp_render, &sp_blend, &time_map.blend);
+        blend_frames(&mut ap_render, &ap_blend, &time_map.blend);
+        (sp_render, ap_render)
+    } else {
+        (
+            interp::interpolate_first_axis(sp, t_render, interp::InterpolatorType::Akima),
+            interp::interpolate_first_axis(ap, t_render, interp::InterpolatorType::Akima),
+        )
+    };
+
+    if let Some(variation) = &variation {
+        variation.apply_spectral(&mut sp_render, &mut ap_render);
+    }
+
+    let gender = render_curve(&curves.gender, flags.gender, &t_sec)?;
+    if gender.iter().any(|g| *g != 0.) {
         println!("Shifting formants.");
-        let shift = (flags.gender / 120.).exp2();
-        formant_shift(&mut sp_render, &mut ap_render, feature_dim as i32, shift);
+        let shift: Vec<f64> = gender.iter().map(|g| (g / 120.).exp2()).collect();
+        formant_shift(&mut sp_render, &mut ap_render, feature_dim as i32, &shift);
+    }
+
+    let tension = render_curve(&curves.tension, 0., &t_sec)?;
+    if tension.iter().any(|t| *t != 0.) {
+        println!("Adjusting tension.");
+        apply_tension(&mut sp_render, &tension);
     }
 
     if flags.fry_enable != 0. {
@@ -186,82 +386,66 @@ pub fn run(args: ResamplerArgs) -> Result<()> {
     }
 
     // render harmonic and aperiodic signals
-    let mut syn_harmonic: Vec<f64> = synthesize_harmonic(&f0_render, &sp_render, &ap_render);
+    let mut syn_harmonic: Vec<f64> =
+        synthesizer.synthesize_harmonic(&f0_render, &sp_render, &ap_render);
     let t_syn: Vec<f64> = util::arange(syn_harmonic.len() as i32)
         .iter()
         .map(|x| x / consts::SAMPLE_RATE as f64)
         .collect();
 
-    if flags.growl != 0. {
+    let growl_mix: Vec<f64> = render_curve(&curves.growl, flags.growl, &t_syn)?
+        .into_iter()
+        .map(|x| x.clamp(0., 100.) / 100.)
+        .collect();
+    if growl_mix.iter().any(|g| *g != 0.) {
         // growl flag. i know.
         println!("Adding growl.");
-        let growl_mix = flags.growl / 100.;
 
         // random normal distrib with standard dev based on growl strength
-        let mut rng = thread_rng();
-        let normal: Normal<f64> = Normal::new(0., growl_mix * growl_mix)?;
-        let f0_layer = f0_render
+        let mut rng = StdRng::seed_from_u64(seed ^ consts::GROWL_SEED);
+        let growl_frames = render_curve(&curves.growl, flags.growl, &t_sec)?;
+        let f0_layer: Vec<f64> = f0_render
             .iter()
-            .map(|x| x * 0.5 * normal.sample(&mut rng).exp2()) // octave + randomness
+            .zip(growl_frames.iter())
+            .map(|(x, growl)| {
+                let growl = growl.clamp(0., 100.) / 100.;
+                let deviation: f64 = StandardNormal.sample(&mut rng);
+                x * 0.5 * (deviation * growl * growl).exp2() // octave + randomness
+            })
             .collect();
-        let mut syn_layer = synthesize_harmonic(&f0_layer, &mut sp_render, &mut ap_render); // growl layer
+        let mut syn_layer = synthesizer.synthesize_harmonic(&f0_layer, &sp_render, &ap_render); // growl layer
 
-        // filter out fundamental of growl layer. expensive i know.
+        // filter out fundamental of growl layer with a filter that follows F0
         let f0_interp = interp::Akima::new(&f0_render);
-        let coeffs = filter::make_coefficients(
-            biquad::Type::HighPass,
-            consts::SAMPLE_RATE as f64,
-            120.,
-            Q_BUTTERWORTH_F64,
-        )?;
-        let mut f0_filter = DirectForm1::<f64>::new(coeffs);
-
-        for _ in 0..2 {
-            syn_layer.iter_mut().zip(t_syn.iter()).for_each(|(x, t)| {
-                let f0_point = f0_interp.sample(t * fps).max(120.);
-                let coeffs = filter::make_coefficients(
-                    biquad::Type::HighPass,
-                    consts::SAMPLE_RATE as f64,
-                    f0_point,
-                    Q_BUTTERWORTH_F64,
-                )
-                .unwrap();
-                f0_filter.update_coefficients(coeffs);
-                *x = f0_filter.run(*x);
-            });
-            f0_filter.reset_state();
-            syn_layer
-                .iter_mut()
-                .rev()
-                .zip(t_syn.iter().rev())
-                .for_each(|(x, t)| {
-                    let f0_point = f0_interp.sample(t * fps).max(120.);
-                    let coeffs = filter::make_coefficients(
-                        biquad::Type::HighPass,
-                        consts::SAMPLE_RATE as f64,
-                        f0_point,
-                        Q_BUTTERWORTH_F64,
-                    )
-                    .unwrap();
-                    f0_filter.update_coefficients(coeffs);
-                    *x = f0_filter.run(*x);
-                });
-            f0_filter.reset_state();
-        }
+        let f0_cutoff: Vec<f64> = f0_interp
+            .sample_with_vec(&t_syn.iter().map(|t| t * fps).collect::<Vec<f64>>())
+            .into_iter()
+            .map(|x| x.max(120.))
+            .collect();
+        let mut f0_filter =
+            filter::StateVariableFilter::new(consts::SAMPLE_RATE as f64, 120., Q_BUTTERWORTH_F64)?;
+        filter::forward_backward_filter_varying(&mut syn_layer, &mut f0_filter, &f0_cutoff, 2);
 
         syn_harmonic
             .iter_mut()
-            .zip(syn_layer.iter())
-            .for_each(|(hm, gw)| *hm = util::lerp(*hm, *gw, growl_mix)); // mix growl layer
+            .zip(syn_layer.iter().zip(growl_mix.iter()))
+            .for_each(|(hm, (gw, mix))| *hm = util::lerp(*hm, *gw, *mix)); // mix growl layer
     }
 
     let syn_aperiodic: Vec<f64> =
-        synthesize_aperiodic(&f0_render, &mut sp_render, &ap_render, true);
+        synthesizer.synthesize_aperiodic(&f0_render, &sp_render, &ap_render, true);
 
-    let harmonic_mix = 1. - 2. * (flags.breathiness / 100. - 0.5);
-    if flags.breathiness != 50. {
+    let harmonic_mix: Vec<f64> = render_curve(&curves.breathiness, flags.breathiness, &t_syn)?
+        .into_iter()
+        .map(|x| 1. - 2. * (x.clamp(0., 100.) / 100. - 0.5))
+        .collect();
+    if harmonic_mix.iter().any(|x| *x != 1.) {
         println!("Adjusting breathiness.");
     }
+    let volume: Vec<f64> = render_curve(&curves.volume, args.volume, &t_syn)?
+        .into_iter()
+        .map(|x| x.max(0.) / 100.)
+        .collect();
 
     // combined logic for all flags related to controlling voicing
     let mut syn: Vec<f64> = if flags.devoice_enable != 0. {
@@ -273,7 +457,8 @@ pub fn run(args: ResamplerArgs) -> Result<()> {
             .iter()
             .zip(syn_aperiodic.iter())
             .zip(t_syn.iter())
-            .map(|((hm, wh), t)| {
+            .zip(harmonic_mix.iter().zip(volume.iter()))
+            .map(|(((hm, wh), t), (harmonic_mix, volume))| {
                 let t = t - consonant - devoice_offset;
                 let amt = smoothstep(
                     -devoice_length - devoice_transition,
@@ -287,25 +472,79 @@ pub fn run(args: ResamplerArgs) -> Result<()> {
         syn_harmonic
             .iter()
             .zip(syn_aperiodic.iter())
-            .map(|(hm, wh)| (hm * harmonic_mix + wh) * volume)
+            .zip(harmonic_mix.iter().zip(volume.iter()))
+            .map(|((hm, wh), (harmonic_mix, volume))| (hm * harmonic_mix + wh) * volume)
             .collect()
     };
 
     if flags.aperiodic_mix != 0. {
         println!("Mixing uncorrected aperiodic.");
         let mix = flags.aperiodic_mix / 100.;
-        let syn_aperiodic = synthesize_aperiodic(&f0_render, &mut sp_render, &ap_render, false);
+        let syn_aperiodic =
+            synthesizer.synthesize_aperiodic(&f0_render, &sp_render, &ap_render, false);
         syn.iter_mut()
             .zip(syn_aperiodic.iter())
             .for_each(|(x, a)| *x = *x * (1. - mix) + a * mix);
     }
 
+    if flags.hybrid_consonant != 0. && consonant > 0. {
+        println!("Using original audio for the consonant.");
+        let audio = read_audio(&args.in_file)?;
+        hybrid_consonant(
+            &mut syn,
+            &audio,
+            t_render,
+            &f0_render,
+            consonant,
+            fps,
+            &volume,
+            flags.hybrid_consonant / 1000.,
+        );
+    }
+
     if flags.tremolo != 0. {
         println!("Adding tremolo.");
-        let mut pitch_raw = pitch_interp.sample_with_vec(&t_pitch);
-        tremolo(&mut syn, &mut pitch_raw, &t_syn, fps, flags.tremolo / 100.)?;
+        let mut pitch_raw = pitch_render.clone();
+        if let Some(vibrato) = vibrato.as_ref().filter(|_| flags.tremolo_follow) {
+            pitch_raw
+                .iter_mut()
+                .zip(vibrato.iter())
+                .for_each(|(pitch, v)| *pitch += v);
+        }
+        tremolo(
+            &mut syn,
+            &pitch_raw,
+            &t_syn,
+            fps,
+            flags.tremolo / 100.,
+            (flags.tremolo_low, flags.tremolo_high),
+        )?;
     }
 
+    if flags.tremolo_depth != 0. {
+        println!("Adding tremolo LFO.");
+        tremolo_lfo(
+            &mut syn,
+            &t_syn,
+            consonant,
+            flags.tremolo_rate,
+            flags.tremolo_depth / 100.,
+            flags.tremolo_phase.to_radians(),
+        );
+    }
+
+    write_render(out_file, syn, out_length, &flags)
+}
+
+fn write_render(
+    out_file: &Path,
+    mut syn: Vec<f64>,
+    out_length: usize,
+    flags: &Flags,
+) -> Result<()> {
+    // final processing shared by every render
+    timing::fit_length(&mut syn, out_length);
+
     if flags.peak_compression != 0. {
         println!("Compressing render.");
         peak_compression(&mut syn, flags.peak_compression / 100.)?;
@@ -320,12 +559,42 @@ pub fn run(args: ResamplerArgs) -> Result<()> {
     Ok(())
 }
 
+fn trim_original(audio: &[f64], start: f64, length: usize, volume: f64) -> Vec<f64> {
+    // cut the render straight from the sample, fading the edges so cuts don't click
+    let fs = consts::SAMPLE_RATE as f64;
+    let start = (start * fs).round() as usize;
+    let duration = length as f64 / fs;
+    (0..length)
+        .map(|i| {
+            let t = i as f64 / fs;
+            let x = audio.get(start + i).copied().unwrap_or(0.);
+            let fade = smoothstep(0., consts::BYPASS_FADE, t)
+                * smoothstep(duration, duration - consts::BYPASS_FADE, t);
+            x * fade * volume
+        })
+        .collect()
+}
+
+fn blend_frames(frames: &mut [Vec<f64>], blend_frames: &[Vec<f64>], blend: &[f64]) {
+    // crossfade between two sets of feature frames
+    frames
+        .iter_mut()
+        .zip(blend_frames.iter().zip(blend.iter()))
+        .filter(|(_, (_, blend))| **blend > 0.)
+        .for_each(|(frame, (blend_frame, blend))| {
+            frame
+                .iter_mut()
+                .zip(blend_frame.iter())
+                .for_each(|(x, y)| *x = util::lerp(*x, *y, *blend));
+        });
+}
+
 // Flag functions
 fn fry(
-    f0: &mut Vec<f64>,
-    sp: &mut Vec<Vec<f64>>,
-    vuv: &Vec<bool>,
-    t: &Vec<f64>,
+    f0: &mut [f64],
+    sp: &mut [Vec<f64>],
+    vuv: &[bool],
+    t: &[f64],
     consonant: f64,
     flags: &Flags,
 ) {
@@ -353,53 +622,118 @@ fn fry(
         });
 }
 
-fn formant_shift(sp: &mut Vec<Vec<f64>>, ap: &mut Vec<Vec<f64>>, feature_dim: i32, shift: f64) {
-    // shift formants by stretching in the frequency domain
-    let freq_t: Vec<f64> = util::arange(feature_dim)
-        .iter()
-        .map(|x| x * shift)
-        .collect();
-    let mask: Vec<f64> = freq_t
-        .iter()
-        .map(|x| smoothstep((feature_dim - 1) as f64, (feature_dim - 2) as f64, *x))
+#[allow(clippy::too_many_arguments)]
+fn hybrid_consonant(
+    signal: &mut [f64],
+    audio: &[f64],
+    t_render: &[f64],
+    f0: &[f64],
+    consonant: f64,
+    fps: f64,
+    volume: &[f64],
+    crossfade: f64,
+) {
+    // keep the original audio before the consonant point and crossfade into the render
+    let fs = consts::SAMPLE_RATE as f64;
+    let half_fade = 0.5 * crossfade;
+    let fade_start = (((consonant - half_fade) * fs).max(0.) as usize).min(signal.len());
+    let fade_end = (((consonant + half_fade) * fs) as usize).min(signal.len());
+
+    // align within one period of the render's pitch at the consonant point
+    let con_frame = ((consonant * fps) as usize).min(f0.len() - 1);
+    let max_lag = if f0[con_frame] > 0. {
+        (fs / f0[con_frame]) as usize
+    } else {
+        0
+    };
+
+    // original audio follows the same timing as the features, so velocity is kept
+    let t_interp = interp::Akima::new(t_render);
+    let source_pos: Vec<f64> = (0..fade_end + max_lag)
+        .map(|i| t_interp.sample(i as f64 * fps / fs) * fs / fps)
         .collect();
+    let original = stretch::wsola(audio, &source_pos);
 
-    sp.iter_mut().for_each(|sp_frame| {
-        let freq_interp = interp::Akima::new(sp_frame);
-        *sp_frame = freq_interp.sample_with_vec(&freq_t);
-        sp_frame
-            .iter_mut()
-            .zip(mask.iter())
-            .for_each(|(s, m)| *s *= *m);
-    });
+    let lag = stretch::best_lag(signal, &original, fade_start..fade_end, max_lag);
+    signal[..fade_end]
+        .iter_mut()
+        .enumerate()
+        .for_each(|(i, x)| {
+            let j = i as isize - lag;
+            let orig = if j < 0 {
+                0.
+            } else {
+                original[j as usize] * volume[i]
+            };
+            let amt = smoothstep(consonant - half_fade, consonant + half_fade, i as f64 / fs);
+            *x = util::lerp(orig, *x, amt);
+        });
+}
 
-    ap.iter_mut().for_each(|ap_frame| {
-        let freq_interp = interp::Akima::new(ap_frame);
-        *ap_frame = freq_interp.sample_with_vec(&freq_t);
-        ap_frame
-            .iter_mut()
-            .zip(mask.iter())
-            .for_each(|(a, m)| *a *= *m);
-    });
+fn formant_shift(sp: &mut [Vec<f64>], ap: &mut [Vec<f64>], feature_dim: i32, shift: &[f64]) {
+    // shift formants by stretching in the frequency domain, with a shift amount per frame
+    let bins = util::arange(feature_dim);
+    sp.iter_mut()
+        .zip(ap.iter_mut())
+        .zip(shift.iter())
+        .for_each(|((sp_frame, ap_frame), shift)| {
+            let freq_t: Vec<f64> = bins.iter().map(|x| x * shift).collect();
+            let mask: Vec<f64> = freq_t
+                .iter()
+                .map(|x| smoothstep((feature_dim - 1) as f64, (feature_dim - 2) as f64, *x))
+                .collect();
+
+            let freq_interp = interp::Akima::new(sp_frame);
+            *sp_frame = freq_interp.sample_with_vec(&freq_t);
+            sp_frame
+                .iter_mut()
+                .zip(mask.iter())
+                .for_each(|(s, m)| *s *= *m);
+
+            let freq_interp = interp::Akima::new(ap_frame);
+            *ap_frame = freq_interp.sample_with_vec(&freq_t);
+            ap_frame
+                .iter_mut()
+                .zip(mask.iter())
+                .for_each(|(a, m)| *a *= *m);
+        });
+}
+
+fn apply_tension(sp: &mut [Vec<f64>], tension: &[f64]) {
+    // tilt the spectral envelope around a pivot, positive tension brightens
+    let fft_size = consts::FFT_SIZE as f64;
+    let fs = consts::SAMPLE_RATE as f64;
+    sp.iter_mut()
+        .zip(tension.iter())
+        .filter(|(_, tension)| **tension != 0.)
+        .for_each(|(sp_frame, tension)| {
+            let slope = 2. * tension.clamp(-100., 100.) / 100.; // power, 6 dB per octave at 100
+            sp_frame.iter_mut().enumerate().for_each(|(k, s)| {
+                let freq = (k as f64 * fs / fft_size).max(consts::TENSION_FLOOR);
+                *s *= (freq / consts::TENSION_PIVOT).powf(slope);
+            });
+        });
 }
 
 fn tremolo(
-    signal: &mut Vec<f64>,
-    pitch: &Vec<f64>,
-    t: &Vec<f64>,
+    signal: &mut [f64],
+    pitch: &[f64],
+    t: &[f64],
     fps: f64,
     strength: f64,
+    band: (f64, f64),
 ) -> Result<()> {
     // Add tremolo to signal based on the pitchbend
     // double approximate derivative leads to approximate inverted vibrato cuz of how the derivative of trig functions work <3
     let tremolo: Vec<f64> = pitch.windows(2).map(|x| x[1] - x[0]).collect();
     let mut tremolo: Vec<f64> = tremolo.windows(2).map(|x| -40. * (x[1] - x[0])).collect(); // -40 is just a value to scale and invert
 
-    // filter out vibratos out of range. vibrato range used is 4~8 Hz. this'll also remove some imprecision from the discrete diffs
+    // filter out vibratos out of range, 4~8 Hz by default. this'll also remove some imprecision from the discrete diffs
+    let (low, high) = band;
     let tremolo_highpass =
-        filter::make_coefficients(biquad::Type::HighPass, fps, 4., Q_BUTTERWORTH_F64)?;
+        filter::make_coefficients(biquad::Type::HighPass, fps, low, Q_BUTTERWORTH_F64)?;
     let tremolo_lowpass =
-        filter::make_coefficients(biquad::Type::LowPass, fps, 8., Q_BUTTERWORTH_F64)?;
+        filter::make_coefficients(biquad::Type::LowPass, fps, high.max(low), Q_BUTTERWORTH_F64)?;
     let mut tremolo_highpass = DirectForm2Transposed::<f64>::new(tremolo_highpass);
     let mut tremolo_lowpass = DirectForm2Transposed::<f64>::new(tremolo_lowpass);
 
@@ -408,7 +742,8 @@ fn tremolo(
 
     // interpolate to sampling rate
     let tremolo_interp = interp::Akima::new(&tremolo);
-    let tremolo_signal = tremolo_interp.sample_with_vec(&t.iter().map(|x| x * fps - 2.).collect());
+    let tremolo_signal =
+        tremolo_interp.sample_with_vec(&t.iter().map(|x| x * fps - 2.).collect::<Vec<f64>>());
 
     // use the isolated vibratos as an envelope
     signal
@@ -418,3 +753,174 @@ fn tremolo(
 
     Ok(())
 }
+
+fn tremolo_lfo(signal: &mut [f64], t: &[f64], start: f64, rate: f64, depth: f64, phase: f64) {
+    // Tremolo from a sine LFO. Volume stays at or below the signal so it never clips
+    signal.iter_mut().zip(t.iter()).for_each(|(x, t)| {
+        let lfo = (std::f64::consts::TAU * rate * (t - start) + phase).cos();
+        *x *= 1. - depth * 0.5 * (1. - lfo);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{run_with, tremolo, tremolo_lfo};
+    use crate::audio::read_write::{read_audio, write_audio};
+    use crate::consts;
+    use crate::parser::ResamplerArgs;
+    use crate::vocoder::backend::{Analysis, Analyzer, Synthesizer};
+    use anyhow::Result;
+    use clap::Parser;
+    use std::cell::RefCell;
+    use std::path::PathBuf;
+
+    struct StubAnalyzer;
+
+    impl Analyzer for StubAnalyzer {
+        fn analyze(&self, audio: &[f64], _threshold: Option<f64>) -> Result<Analysis> {
+            // 200 Hz for the 100 ms consonant, 400 Hz after it
+            let hop = consts::SAMPLE_RATE as f64 * consts::FRAME_PERIOD / 1000.;
+            let frames = (audio.len() as f64 / hop) as usize + 1;
+            let dims = (consts::FFT_SIZE / 2 + 1) as usize;
+            Ok(Analysis {
+                f0: (0..frames)
+                    .map(|i| if i < 20 { 200. } else { 400. })
+                    .collect(),
+                sp: vec![vec![1e-4; dims]; frames],
+                ap: vec![vec![0.1; dims]; frames],
+            })
+        }
+    }
+
+    #[derive(Default)]
+    struct StubSynthesizer {
+        f0: RefCell<Vec<f64>>,
+    }
+
+    impl Synthesizer for StubSynthesizer {
+        fn synthesize(&self, f0: &[f64], _sp: &[Vec<f64>], _ap: &[Vec<f64>]) -> Vec<f64> {
+            // remembers the F0 it was asked for, one hop of samples per frame
+            self.f0.replace(f0.to_vec());
+            let hop = consts::SAMPLE_RATE as f64 * consts::FRAME_PERIOD / 1000.;
+            (0..(f0.len() as f64 * hop) as usize)
+                .map(|i| 0.1 * (i as f64 * 0.05).sin())
+                .collect()
+        }
+    }
+
+    fn render(
+        name: &str,
+        note: &str,
+        velocity: f64,
+        flags: &str,
+        length: f64,
+    ) -> (usize, Vec<f64>) {
+        // render a 1 s sample with a 100 ms consonant through the stub backends
+        let dir = std::env::temp_dir().join(format!("straycat-rs-{}", name));
+        std::fs::create_dir_all(&dir).expect("Cannot create directory");
+        let in_file = dir.join("in.wav");
+        let out_file = dir.join("out.wav");
+        let audio: Vec<f64> = (0..consts::SAMPLE_RATE)
+            .map(|i| 0.1 * (i as f64 * 0.05).sin())
+            .collect();
+        write_audio(&in_file, &audio).expect("Cannot write");
+
+        let path = |p: &PathBuf| p.to_str().unwrap().to_string();
+        let args = ResamplerArgs::parse_from([
+            "straycat-rs".to_string(),
+            path(&in_file),
+            path(&out_file),
+            note.to_string(),
+            velocity.to_string(),
+            flags.to_string(),
+            "0".to_string(),
+            length.to_string(),
+            "100".to_string(),
+            "0".to_string(),
+            "100".to_string(),
+            "100".to_string(),
+            "!120".to_string(),
+            "AA".to_string(),
+        ]);
+        let flags = args.flags.parse().expect("Cannot parse flags");
+        let synthesizer = StubSynthesizer::default();
+        run_with(args, flags, 0, &StubAnalyzer, &synthesizer).expect("Cannot render");
+        let out = read_audio(&out_file).expect("Cannot read");
+        (out.len(), synthesizer.f0.take())
+    }
+
+    #[test]
+    fn test_render_length() {
+        // output is exactly the stretched consonant plus the length, and the consonant ends
+        // on the closest frame to where velocity puts it
+        for length in [0., 37.3, 333.3, 1234.5] {
+            for velocity in [0., 25., 50., 100., 150., 200.] {
+                let consonant = 100. * (1. - velocity / 100_f64).exp2();
+                let (samples, f0) = render("length", "A4", velocity, "", length);
+                assert_eq!(samples, ((consonant + length) * 44.1).round() as usize);
+
+                let max = f0.iter().fold(0., |m: f64, x| m.max(*x));
+                let boundary = f0.iter().position(|x| *x >= max - 1e-6).unwrap();
+                let error = boundary as f64 * consts::FRAME_PERIOD - consonant;
+                assert!(
+                    error.abs() <= consts::FRAME_PERIOD / 2.,
+                    "velocity {velocity}, length {length}: {error} ms"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_render_tuning() {
+        // the tuning moves the note, t and the sample's own pitch stay a ratio on top of it
+        let ratio = |flags, note| {
+            let (_, f0) = render("tuning", note, 100., flags, 200.);
+            let (_, f0_12) = render("tuning", "A4", 100., "", 200.);
+            let cents: Vec<f64> = f0
+                .iter()
+                .zip(f0_12.iter())
+                .filter(|(f0, _)| **f0 > 0.)
+                .map(|(f0, f0_12)| 1200. * (f0 / f0_12).log2())
+                .collect();
+            assert!(!cents.is_empty());
+            assert!(cents.iter().all(|x| (x - cents[0]).abs() < 1e-6));
+            cents[0]
+        };
+        // C5 is 12 steps of 50 cents above C4, A4 is 9 steps and stays at 440 Hz
+        assert!((ratio("Te24", "C5") - 150.).abs() < 1e-6);
+        assert!((ratio("t50Te24", "C5") - 200.).abs() < 1e-6);
+        assert!((ratio("t-25", "A4") + 25.).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_tremolo_lfo() {
+        let t: Vec<f64> = (0..44100).map(|i| i as f64 / 44100.).collect();
+        let mut signal = vec![1.; t.len()];
+        tremolo_lfo(&mut signal, &t, 0.25, 4., 0.6, 0.);
+        // full volume at the start point, dips to 1 - depth half a cycle later
+        assert!((signal[11025] - 1.).abs() < 1e-9);
+        assert!((signal[11025 + 5512] - 0.4).abs() < 1e-3);
+        assert!(signal.iter().all(|x| (0.4 - 1e-9..=1.).contains(x)));
+    }
+
+    #[test]
+    fn test_tremolo_band() {
+        // a 2 Hz vibrato is filtered out by the default band but followed with a lower edge
+        let fps = 200.;
+        let pitch: Vec<f64> = (0..800)
+            .map(|i| 60. + 0.5 * (std::f64::consts::TAU * 2. * i as f64 / fps).sin())
+            .collect();
+        let t: Vec<f64> = (0..800 * 220).map(|i| i as f64 / 44100.).collect();
+        let depth = |band| {
+            let mut signal = vec![1.; t.len()];
+            tremolo(&mut signal, &pitch, &t, fps, 1., band).unwrap();
+            let mid = &signal[signal.len() / 4..3 * signal.len() / 4];
+            let max = mid.iter().fold(f64::MIN, |m, x| m.max(*x));
+            let min = mid.iter().fold(f64::MAX, |m, x| m.min(*x));
+            max / min
+        };
+        let (slow, default) = (depth((1., 3.)), depth((4., 8.)));
+        assert!(slow > 1.03);
+        assert!(slow - 1. > 4. * (default - 1.));
+    }
+}
@@ -0,0 +1,443 @@
+use crate::{consts, util};
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum StretchMode {
+    #[default]
+    Stretch,
+    Loop,
+    PingPong,
+}
+
+impl StretchMode {
+    pub fn from_value(value: f64) -> Self {
+        match value.round() as i32 {
+            1 => Self::Loop,
+            2 => Self::PingPong,
+            _ => Self::Stretch,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ConsonantMode {
+    #[default]
+    Uniform,
+    Closure,
+    KeepBursts,
+    Ease,
+}
+
+impl ConsonantMode {
+    pub fn from_value(value: f64) -> Self {
+        match value.round() as i32 {
+            1 => Self::Closure,
+            2 => Self::KeepBursts,
+            3 => Self::Ease,
+            _ => Self::Uniform,
+        }
+    }
+}
+
+pub struct TimeMap {
+    // Position in the source for each render frame, in frames
+    pub t: Vec<f64>,
+    // Second position that gets crossfaded in, and how much of it to use
+    pub t_blend: Vec<f64>,
+    pub blend: Vec<f64>,
+}
+
+impl TimeMap {
+    pub fn new(t: Vec<f64>) -> Self {
+        let t_blend = t.clone();
+        let blend = vec![0.; t.len()];
+        Self { t, t_blend, blend }
+    }
+
+    pub fn append(&mut self, mut other: TimeMap) {
+        self.t.append(&mut other.t);
+        self.t_blend.append(&mut other.t_blend);
+        self.blend.append(&mut other.blend);
+    }
+
+    pub fn len(&self) -> usize {
+        self.t.len()
+    }
+
+    pub fn is_blended(&self) -> bool {
+        self.blend.iter().any(|x| *x > 0.)
+    }
+}
+
+pub fn output_samples(duration: f64) -> usize {
+    // exact output length in samples for a duration in milliseconds
+    (duration.max(0.) * consts::SAMPLE_RATE as f64 / 1000.).round() as usize
+}
+
+pub fn frames_for_samples(samples: usize) -> usize {
+    // smallest frame count whose synthesis covers a number of samples, with one spare frame
+    let hop = consts::SAMPLE_RATE as f64 * consts::FRAME_PERIOD / 1000.;
+    (samples as f64 / hop).ceil() as usize + 1
+}
+
+pub fn fit_length(signal: &mut Vec<f64>, length: usize) {
+    // trim or pad the end of a render so it is exactly the requested length
+    signal.resize(length, 0.);
+}
+
+pub fn frame_energy(sp: &[Vec<f64>]) -> Vec<f64> {
+    // energy of every spectral envelope frame in dB
+    sp.iter()
+        .map(|frame| 10. * (frame.iter().sum::<f64>() + 1e-16).log10())
+        .collect()
+}
+
+fn consonant_weights(
+    mode: ConsonantMode,
+    start: f64,
+    unit: f64,
+    units: usize,
+    energy: &[f64],
+    vuv: &[bool],
+) -> Vec<f64> {
+    // How much each part of the consonant takes the stretch. Closures are quiet unvoiced frames,
+    // bursts are unvoiced frames right after a sudden rise in energy.
+    let frame = |i: usize| ((start + i as f64 * unit) as usize).min(energy.len() - 1);
+    let peak = (0..units)
+        .map(|i| energy[frame(i)])
+        .fold(f64::NEG_INFINITY, f64::max);
+    let closure = |i: usize| !vuv[frame(i)] && energy[frame(i)] < peak - consts::CLOSURE_DB;
+    let rise = |i: usize| {
+        let f = frame(i);
+        f >= 2 && !vuv[f] && energy[f] - energy[f - 2] > consts::BURST_RISE_DB
+    };
+    let burst: Vec<bool> = (0..units)
+        .map(|i| (0..=consts::BURST_SPREAD.min(i)).any(|k| rise(i - k)))
+        .collect();
+
+    (0..units)
+        .map(|i| match mode {
+            ConsonantMode::Uniform => 1.,
+            ConsonantMode::Closure => closure(i) as u8 as f64,
+            ConsonantMode::KeepBursts => !burst[i] as u8 as f64,
+            ConsonantMode::Ease => 1. - i as f64 / units as f64,
+        })
+        .collect()
+}
+
+pub fn consonant_map(
+    mode: ConsonantMode,
+    start: f64,
+    end: f64,
+    frames: usize,
+    energy: &[f64],
+    vuv: &[bool],
+) -> TimeMap {
+    // Make the time map for the consonant, start and end are in source frames. Velocity sets the
+    // overall length, the mode decides which parts of the consonant take the stretch.
+    let length = end - start;
+    let units = length.round() as usize;
+    if mode == ConsonantMode::Uniform || units == 0 || frames == 0 || energy.is_empty() {
+        return TimeMap::new(util::linspace(start, end, frames, false));
+    }
+    let unit = length / units as f64;
+
+    // duration of every source unit in render frames, d = unit * (1 + w * k)
+    let weights = consonant_weights(mode, start, unit, units, energy, vuv);
+    let weight_sum: f64 = weights.iter().sum();
+    let k = (frames as f64 / length - 1.) * units as f64 / weight_sum;
+    let durations: Vec<f64> = weights.iter().map(|w| unit * (1. + w * k)).collect();
+    if weight_sum <= 0.
+        || durations
+            .iter()
+            .any(|d| *d < consts::MIN_CONSONANT_SPEED * unit)
+    {
+        // nothing to stretch, or the consonant would have to be squeezed too much
+        return TimeMap::new(util::linspace(start, end, frames, false));
+    }
+
+    // walk through the units for every render frame
+    let mut t = Vec::with_capacity(frames);
+    let mut unit_start = 0.;
+    let mut i = 0;
+    for j in 0..frames {
+        let j = j as f64;
+        while i < units - 1 && unit_start + durations[i] <= j {
+            unit_start += durations[i];
+            i += 1;
+        }
+        let r = ((j - unit_start) / durations[i]).min(1.);
+        t.push(start + (i as f64 + r) * unit);
+    }
+    TimeMap::new(t)
+}
+
+pub fn stable_region(instability: &[f64], start: f64, end: f64) -> (f64, f64) {
+    // Find the span between start and end (in frames) with the lowest average instability
+    let first = start.ceil().max(0.) as usize;
+    let last = (end.floor().max(0.) as usize).min(instability.len());
+    if last <= first {
+        return (start, end);
+    }
+    let region = last - first;
+    let span = ((region as f64 * consts::STABLE_REGION_RATIO).max(consts::STABLE_REGION_MIN)
+        as usize)
+        .min(region);
+    if span == region {
+        return (start, end);
+    }
+
+    // sliding window sum
+    let mut sum: f64 = instability[first..first + span].iter().sum();
+    let mut best = (sum, first);
+    for i in first + 1..=last - span {
+        sum += instability[i + span - 1] - instability[i - 1];
+        if sum < best.0 {
+            best = (sum, i);
+        }
+    }
+    (best.1 as f64, (best.1 + span) as f64)
+}
+
+pub fn stretch_map(
+    mode: StretchMode,
+    start: f64,
+    region: (f64, f64),
+    end: f64,
+    frames: usize,
+    crossfade: f64,
+) -> TimeMap {
+    // Make the time map for the part after the consonant. The onset before the region and the
+    // release after it play at natural speed, only the region gets stretched, looped or ping-ponged.
+    let (region_start, region_end) = region;
+    let onset = (region_start - start).floor().max(0.) as usize;
+    let release = (end - region_end).floor().max(0.) as usize;
+    if onset + release + 2 > frames {
+        return TimeMap::new(util::linspace(start, end, frames, true));
+    }
+    let middle = frames - onset - release;
+    let region_start = start + onset as f64;
+    let region_end = end - release as f64;
+
+    let mut map = TimeMap::new((0..onset).map(|i| start + i as f64).collect());
+    let mut section = match mode {
+        StretchMode::Stretch => {
+            TimeMap::new(util::linspace(region_start, region_end, middle, false))
+        }
+        StretchMode::Loop => loop_section(region_start, region_end, middle, crossfade),
+        StretchMode::PingPong => ping_pong_section(region_start, region_end, middle),
+    };
+
+    // loops stop anywhere in the region, crossfade into the lead up to the release
+    if mode != StretchMode::Stretch {
+        let fade = (crossfade.min(0.5 * middle as f64) as usize).min(middle);
+        for k in 0..fade {
+            let i = middle - fade + k;
+            section.t_blend[i] = (region_end - (fade - k) as f64).max(region_start);
+            section.blend[i] = util::smoothstep(0., fade as f64, (k + 1) as f64);
+        }
+    }
+    map.append(section);
+    map.append(TimeMap::new(
+        (0..release).map(|i| region_end + i as f64).collect(),
+    ));
+    map
+}
+
+fn loop_section(start: f64, end: f64, frames: usize, crossfade: f64) -> TimeMap {
+    // Play the region at natural speed and loop it. The loop end keeps playing past the loop
+    // point while fading out so the loop seam is crossfaded.
+    let crossfade = crossfade.min(0.5 * (end - start)).max(0.);
+    let loop_end = end - crossfade;
+    let loop_length = loop_end - start;
+    if loop_length <= 1. {
+        return TimeMap::new(util::linspace(start, end, frames, false));
+    }
+
+    let mut map = TimeMap::new(Vec::with_capacity(frames));
+    for i in 0..frames {
+        let i = i as f64;
+        let cycle = (i / loop_length).floor();
+        let t = start + i - cycle * loop_length;
+        let since_loop = t - start;
+        if cycle > 0. && since_loop < crossfade {
+            map.t.push(t);
+            map.t_blend.push(t + loop_length);
+            map.blend.push(util::smoothstep(crossfade, 0., since_loop));
+        } else {
+            map.t.push(t);
+            map.t_blend.push(t);
+            map.blend.push(0.);
+        }
+    }
+    map
+}
+
+fn ping_pong_section(start: f64, end: f64, frames: usize) -> TimeMap {
+    // Play the region at natural speed back and forth
+    let length = end - start;
+    if length <= 1. {
+        return TimeMap::new(util::linspace(start, end, frames, false));
+    }
+
+    let t = (0..frames)
+        .map(|i| {
+            let phase = (i as f64) % (2. * length);
+            if phase <= length {
+                start + phase
+            } else {
+                end - (phase - length)
+            }
+        })
+        .collect();
+    TimeMap::new(t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        consonant_map, fit_length, frames_for_samples, loop_section, output_samples,
+        ping_pong_section, stable_region, stretch_map, ConsonantMode, StretchMode,
+    };
+    use crate::consts;
+    use crate::vocoder::backend::Synthesizer;
+    use crate::vocoder::hnm::HarmonicNoise;
+    use crate::world::vocoder::World;
+
+    #[test]
+    fn test_frames_for_samples() {
+        // both synthesizers cover every output length with the frames they are given
+        let dims = (consts::FFT_SIZE / 2 + 1) as usize;
+        for length in [0., 1., 4.9, 5., 37.3, 100., 333.3, 1234.5] {
+            for velocity in [0., 25., 50., 100., 150., 200.] {
+                let consonant = 87.6 * (1. - velocity / 100_f64).exp2();
+                let samples = output_samples(consonant + length);
+                let frames = frames_for_samples(samples);
+                let f0 = vec![220.; frames];
+                let sp = vec![vec![1e-4; dims]; frames];
+                let ap = vec![vec![0.5; dims]; frames];
+                for mut syn in [
+                    World.synthesize(&f0, &sp, &ap),
+                    HarmonicNoise::default().synthesize(&f0, &sp, &ap),
+                ] {
+                    assert!(syn.len() >= samples);
+                    fit_length(&mut syn, samples);
+                    assert_eq!(syn.len(), samples);
+                }
+            }
+        }
+
+        // short renders get padded
+        let mut syn = vec![1.; 10];
+        fit_length(&mut syn, 15);
+        assert_eq!(syn, [vec![1.; 10], vec![0.; 5]].concat());
+    }
+
+    #[test]
+    fn test_loop_section() {
+        let map = loop_section(10., 50., 200, 8.);
+        assert_eq!(map.len(), 200);
+        // natural speed, never past the region
+        assert!(map.t.iter().all(|t| *t >= 10. && *t < 42.));
+        assert!(map.t_blend.iter().all(|t| *t <= 50.));
+        assert_eq!(map.t[1] - map.t[0], 1.);
+        // seam is fully the continuation of the loop end
+        assert_eq!(map.t[32], 10.);
+        assert_eq!(map.t_blend[32], 42.);
+        assert_eq!(map.blend[32], 1.);
+        assert_eq!(map.blend[40], 0.);
+    }
+
+    #[test]
+    fn test_ping_pong_section() {
+        let map = ping_pong_section(10., 20., 45);
+        assert!(!map.is_blended());
+        let expected = [10., 15., 20., 15., 10., 15., 20., 15., 10.];
+        map.t
+            .iter()
+            .step_by(5)
+            .zip(expected.iter())
+            .for_each(|(t, e)| assert_eq!(t, e));
+    }
+
+    #[test]
+    fn test_stable_region() {
+        // a calm dip in the middle of a noisy curve
+        let instability: Vec<f64> = (0..100)
+            .map(|i| if (45..85).contains(&i) { 0.1 } else { 2. })
+            .collect();
+        let (a, b) = stable_region(&instability, 20., 100.);
+        assert!(a >= 45. && b <= 85.);
+        assert!(b - a >= 20.);
+
+        // region too short to pick from
+        assert_eq!(stable_region(&instability, 20., 30.), (20., 30.));
+    }
+
+    #[test]
+    fn test_stretch_map() {
+        // onset and release at natural speed, region stretched
+        let map = stretch_map(StretchMode::Stretch, 10., (30., 50.), 70., 200, 20.);
+        assert_eq!(map.len(), 200);
+        assert_eq!(map.t[0], 10.);
+        assert_eq!(map.t[19], 29.);
+        assert_eq!(map.t[199], 69.);
+        assert_eq!(map.t[180], 50.);
+        assert!(map.t.windows(2).all(|w| w[1] > w[0]));
+        assert!(!map.is_blended());
+
+        // loops blend into the release
+        let map = stretch_map(StretchMode::Loop, 10., (30., 50.), 70., 200, 4.);
+        assert_eq!(map.len(), 200);
+        assert_eq!(map.t[180], 50.);
+        assert_eq!(map.blend[179], 1.);
+        assert_eq!(map.t_blend[179], 49.);
+    }
+
+    #[test]
+    fn test_consonant_map() {
+        // silence, burst, aspiration, then the vowel
+        let energy: Vec<f64> = (0..40)
+            .map(|i| match i {
+                0..=9 => -80.,
+                10..=11 => -20.,
+                12..=19 => -35.,
+                _ => -10.,
+            })
+            .collect();
+        let vuv: Vec<bool> = (0..40).map(|i| i >= 20).collect();
+        let speed = |t: &[f64], i: usize| t[i + 1] - t[i];
+
+        for mode in [
+            ConsonantMode::Uniform,
+            ConsonantMode::Closure,
+            ConsonantMode::KeepBursts,
+            ConsonantMode::Ease,
+        ] {
+            // all modes cover the consonant in order with the requested length
+            let map = consonant_map(mode, 0., 20., 40, &energy, &vuv);
+            assert_eq!(map.len(), 40);
+            assert_eq!(map.t[0], 0.);
+            assert!(map.t.windows(2).all(|w| w[1] >= w[0]));
+            assert!(*map.t.last().unwrap() < 20.);
+        }
+
+        // closure takes all the stretch, the rest plays at natural speed
+        let map = consonant_map(ConsonantMode::Closure, 0., 20., 40, &energy, &vuv);
+        assert!((speed(&map.t, 5) - 10. / 30.).abs() < 1e-9);
+        assert!((speed(&map.t, 35) - 1.).abs() < 1e-9);
+
+        // bursts play at natural speed, everything else is slower
+        let map = consonant_map(ConsonantMode::KeepBursts, 0., 20., 40, &energy, &vuv);
+        let burst = map.t.iter().position(|t| *t >= 10.).unwrap();
+        assert!((speed(&map.t, burst) - 1.).abs() < 1e-9);
+        assert!(speed(&map.t, 2) < 0.5);
+
+        // easing slows the start the most
+        let map = consonant_map(ConsonantMode::Ease, 0., 20., 40, &energy, &vuv);
+        assert!(speed(&map.t, 0) < speed(&map.t, 38));
+
+        // uniform matches plain velocity
+        let map = consonant_map(ConsonantMode::Uniform, 0., 20., 40, &energy, &vuv);
+        assert_eq!(map.t, crate::util::linspace(0., 20., 40, false));
+    }
+}
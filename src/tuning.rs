@@ -0,0 +1,324 @@
+use anyhow::{anyhow, Result};
+use std::fs;
+use std::path::Path;
+
+use crate::{consts, util};
+
+// Maps MIDI keys to scale degrees and pins one key to a frequency, like a Scala .kbm file
+#[derive(Debug, Clone, PartialEq)]
+pub struct Keymap {
+    // keys in one repeat of the mapping, 0 maps every key to the next degree
+    pub size: usize,
+    // key that plays degree 0
+    pub middle: i32,
+    // key that plays the reference frequency
+    pub reference: i32,
+    pub frequency: f64,
+    // degree that the mapping repeats at, 0 means the period of the scale
+    pub octave_degree: usize,
+    // degree of every key in the mapping, None keys are not mapped
+    pub mapping: Vec<Option<usize>>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        // Scala's default mapping, degree 0 on C4 and A4 at 440 Hz
+        Self {
+            size: 0,
+            middle: 60,
+            reference: 69,
+            frequency: 440.,
+            octave_degree: 0,
+            mapping: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tuning {
+    // cents of every degree above degree 0, the last one is the period of the scale
+    pub degrees: Vec<f64>,
+    pub keymap: Keymap,
+}
+
+impl Default for Tuning {
+    fn default() -> Self {
+        Self::edo(12.)
+    }
+}
+
+impl Tuning {
+    pub fn edo(steps: f64) -> Self {
+        // Equal division of the octave
+        let steps = steps.round().max(1.) as usize;
+        Self {
+            degrees: (1..=steps)
+                .map(|i| 1200. * i as f64 / steps as f64)
+                .collect(),
+            keymap: Keymap::default(),
+        }
+    }
+
+    pub fn load(
+        scl: Option<&str>,
+        kbm: Option<&str>,
+        reference: Option<f64>,
+        edo: Option<f64>,
+    ) -> Result<Self> {
+        // Tuning from the EDO flag, a scale file or 12-TET, in that order. Scale and keymap files
+        // can be passed on the command line or put next to the resampler
+        let scl = scl
+            .map(|path| Path::new(path).to_path_buf())
+            .or_else(|| util::resampler_file(consts::SCALE_FILE).filter(|path| path.exists()));
+        let kbm = kbm
+            .map(|path| Path::new(path).to_path_buf())
+            .or_else(|| util::resampler_file(consts::KEYMAP_FILE).filter(|path| path.exists()));
+
+        let mut tuning = match (edo, &scl) {
+            (Some(steps), _) => Self::edo(steps),
+            (None, Some(path)) => {
+                println!("Reading scale from {}.", path.display());
+                Self {
+                    degrees: parse_scl(&fs::read_to_string(path)?)?,
+                    keymap: Keymap::default(),
+                }
+            }
+            (None, None) => Self::default(),
+        };
+        if let Some(path) = kbm {
+            println!("Reading keymap from {}.", path.display());
+            tuning.keymap = parse_kbm(&fs::read_to_string(path)?)?;
+        }
+        if let Some(reference) = reference {
+            tuning.keymap.frequency = reference;
+        }
+        if tuning.key_cents(tuning.keymap.reference).is_none() {
+            return Err(anyhow!("Reference key of the keymap is not mapped."));
+        }
+        Ok(tuning)
+    }
+
+    pub fn is_standard(&self) -> bool {
+        // 12-TET with A4 at 440 Hz, the tuning the sample analysis assumes
+        *self == Self::default()
+    }
+
+    fn degree_cents(&self, degree: i64) -> f64 {
+        // cents of any degree, degrees past the end repeat at the period
+        let n = self.degrees.len() as i64;
+        let period = self.degrees[self.degrees.len() - 1];
+        let i = degree.rem_euclid(n) as usize;
+        let cents = if i == 0 { 0. } else { self.degrees[i - 1] };
+        degree.div_euclid(n) as f64 * period + cents
+    }
+
+    fn key_cents(&self, key: i32) -> Option<f64> {
+        // cents of a key above degree 0, None if the keymap leaves it out
+        let keymap = &self.keymap;
+        let d = (key - keymap.middle) as i64;
+        let degree = if keymap.size == 0 {
+            d
+        } else {
+            let size = keymap.size as i64;
+            let octave_degree = match keymap.octave_degree {
+                0 => self.degrees.len(),
+                x => x,
+            } as i64;
+            let mapped = (*keymap.mapping.get(d.rem_euclid(size) as usize)?)? as i64;
+            d.div_euclid(size) * octave_degree + mapped
+        };
+        Some(self.degree_cents(degree))
+    }
+
+    fn key_hz(&self, key: i32) -> Option<f64> {
+        let cents = self.key_cents(key)? - self.key_cents(self.keymap.reference)?;
+        Some(self.keymap.frequency * (cents / 1200.).exp2())
+    }
+
+    pub fn midi_to_hz(&self, midi: f64) -> f64 {
+        // Frequency of a fractional key. Whole keys play their tuned pitch and anything in
+        // between, like pitchbends, glides evenly to the next mapped key
+        let search = self.keymap.size.max(1) as i32;
+        let lo = midi.floor() as i32;
+        let below = (0..search)
+            .map(|i| lo - i)
+            .find_map(|key| Some((key, self.key_hz(key)?)));
+        let above = (1..=search)
+            .map(|i| lo + i)
+            .find_map(|key| Some((key, self.key_hz(key)?)));
+        match (below, above) {
+            (Some((a, hz_a)), Some((b, hz_b))) => {
+                hz_a * (hz_b / hz_a).powf((midi - a as f64) / (b - a) as f64)
+            }
+            _ => util::midi_to_hz(midi),
+        }
+    }
+}
+
+fn parse_pitch(s: &str) -> Result<f64> {
+    // Scala pitches are cents if they have a period, otherwise a ratio like 3/2 or 2
+    let invalid = || anyhow!("Invalid pitch `{s}` in scale file.");
+    if s.contains('.') {
+        return s.parse().map_err(|_| invalid());
+    }
+    let (num, den) = s.split_once('/').unwrap_or((s, "1"));
+    let num: f64 = num.parse().map_err(|_| invalid())?;
+    let den: f64 = den.parse().map_err(|_| invalid())?;
+    if num <= 0. || den <= 0. {
+        return Err(invalid());
+    }
+    Ok(1200. * (num / den).log2())
+}
+
+pub fn parse_scl(s: &str) -> Result<Vec<f64>> {
+    // Scala scale file. Lines starting with ! are comments, then a description, the note count
+    // and one pitch per note. Anything after the pitch on a line is ignored
+    let mut lines = s.lines().filter(|line| !line.starts_with('!'));
+    lines.next(); // description
+    let count: usize = lines
+        .next()
+        .and_then(|line| line.split_whitespace().next())
+        .and_then(|count| count.parse().ok())
+        .ok_or_else(|| anyhow!("Scale file has no note count."))?;
+    if count == 0 {
+        return Err(anyhow!("Scale file has no notes."));
+    }
+    let degrees = lines
+        .filter_map(|line| line.split_whitespace().next())
+        .take(count)
+        .map(parse_pitch)
+        .collect::<Result<Vec<f64>>>()?;
+    if degrees.len() != count {
+        return Err(anyhow!(
+            "Scale file has {count} notes but lists {}.",
+            degrees.len()
+        ));
+    }
+    Ok(degrees)
+}
+
+pub fn parse_kbm(s: &str) -> Result<Keymap> {
+    // Scala keymap file. The first and last key to retune are read but every key is retuned
+    let mut values = s
+        .lines()
+        .filter(|line| !line.starts_with('!'))
+        .filter_map(|line| line.split_whitespace().next());
+    let mut header = |name: &str| {
+        values
+            .next()
+            .ok_or_else(|| anyhow!("Keymap file has no {name}."))
+    };
+    let invalid = |name: &str| anyhow!("Keymap file has an invalid {name}.");
+    let size: usize = header("map size")?
+        .parse()
+        .map_err(|_| invalid("map size"))?;
+    header("first key")?;
+    header("last key")?;
+    let middle: i32 = header("middle key")?
+        .parse()
+        .map_err(|_| invalid("middle key"))?;
+    let reference: i32 = header("reference key")?
+        .parse()
+        .map_err(|_| invalid("reference key"))?;
+    let frequency: f64 = header("reference frequency")?
+        .parse()
+        .map_err(|_| invalid("reference frequency"))?;
+    let octave_degree: usize = header("octave degree")?
+        .parse()
+        .map_err(|_| invalid("octave degree"))?;
+
+    // missing entries at the end are not mapped
+    let mut mapping = values
+        .take(size)
+        .map(|v| match v {
+            "x" | "X" => Ok(None),
+            v => v.parse().map(Some).map_err(|_| invalid("mapping")),
+        })
+        .collect::<Result<Vec<Option<usize>>>>()?;
+    mapping.resize(size, None);
+    if size > 0 && mapping.iter().all(Option::is_none) {
+        return Err(anyhow!("Keymap file maps no keys."));
+    }
+    if frequency <= 0. {
+        return Err(invalid("reference frequency"));
+    }
+    Ok(Keymap {
+        size,
+        middle,
+        reference,
+        frequency,
+        octave_degree,
+        mapping,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_kbm, parse_scl, Tuning};
+    use crate::util;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-6 * b
+    }
+
+    #[test]
+    fn test_edo() {
+        // 12-TET matches the usual formula, fractional keys included
+        let tuning = Tuning::default();
+        assert!(tuning.is_standard());
+        for midi in [0., 21.5, 60., 68.75, 69., 127.] {
+            assert!(close(tuning.midi_to_hz(midi), util::midi_to_hz(midi)));
+        }
+
+        // 24-EDO puts a quarter tone between keys, A4 stays at the reference
+        let tuning = Tuning::load(None, None, Some(432.), Some(24.)).unwrap();
+        assert!(!tuning.is_standard());
+        assert!(close(tuning.midi_to_hz(69.), 432.));
+        assert!(close(tuning.midi_to_hz(70.), 432. * 2_f64.powf(1. / 24.)));
+        assert!(close(tuning.midi_to_hz(93.), 864.));
+    }
+
+    #[test]
+    fn test_scl() {
+        let scl =
+            "! just.scl\n!\n5-limit just major\n 7\n!\n9/8\n5/4\n4/3\n3/2 fifth\n5/3\n15/8\n2/1\n";
+        let degrees = parse_scl(scl).unwrap();
+        assert_eq!(degrees.len(), 7);
+        assert!(close(degrees[3], 701.955));
+        assert!(close(degrees[6], 1200.));
+        assert!(close(parse_scl("\n1\n100.5 cents\n").unwrap()[0], 100.5));
+
+        assert!(parse_scl("bad\n3\n9/8\n").is_err());
+        assert!(parse_scl("bad\n2\n9/8\nx\n").is_err());
+        assert!(parse_scl("bad\n1\n-3/2\n").is_err());
+        assert!(parse_scl("no count\n").is_err());
+    }
+
+    #[test]
+    fn test_kbm() {
+        // just major on the white keys of C, A4 at 440 Hz
+        let scl = "just\n7\n9/8\n5/4\n4/3\n3/2\n5/3\n15/8\n2/1\n";
+        let kbm = "! white keys\n12\n0\n127\n60\n69\n440.0\n7\n! mapping\n0\nx\n1\nx\n2\n3\nx\n4\nx\n5\nx\n6\n";
+        let tuning = Tuning {
+            degrees: parse_scl(scl).unwrap(),
+            keymap: parse_kbm(kbm).unwrap(),
+        };
+        assert!(close(tuning.midi_to_hz(69.), 440.));
+        // C is a just major sixth below A
+        let c4 = 440. * 3. / 5.;
+        assert!(close(tuning.midi_to_hz(60.), c4));
+        assert!(close(tuning.midi_to_hz(67.), c4 * 1.5));
+        assert!(close(tuning.midi_to_hz(72.), c4 * 2.));
+        assert!(close(tuning.midi_to_hz(48.), c4 / 2.));
+        // black keys are not mapped and glide between their neighbors
+        assert!(close(tuning.midi_to_hz(61.), c4 * (9_f64 / 8.).sqrt()));
+
+        assert!(parse_kbm("12\n0\n127\n60\n69\n").is_err());
+        assert!(parse_kbm("2\n0\n127\n60\n69\n440\n0\nx\nx\n").is_err());
+        // short mappings are padded with unmapped keys
+        assert_eq!(
+            parse_kbm("3\n0\n127\n60\n69\n440\n0\n0\n").unwrap().mapping,
+            vec![Some(0), None, None]
+        );
+    }
+}
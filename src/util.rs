@@ -1,5 +1,6 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use regex::Regex;
+use std::path::PathBuf;
 
 // Rust versions of some numpy operations
 pub fn arange(end: i32) -> Vec<f64> {
@@ -49,40 +50,61 @@ pub fn midi_to_hz(x: f64) -> f64 {
     440. * ((x - 69.) / 12.).exp2()
 }
 
+pub fn hz_to_midi(x: f64) -> f64 {
+    // Convert Hertz to MIDI numbers
+    69. + 12. * (x / 440.).log2()
+}
+
+pub fn resampler_file(name: &str) -> Option<PathBuf> {
+    // File next to the resampler executable
+    let exe = std::env::current_exe().ok()?;
+    Some(exe.parent()?.join(name))
+}
+
+pub fn hash(bytes: &[u8]) -> u64 {
+    // FNV-1a, stable across builds and platforms unlike the std hasher
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |h, b| {
+        (h ^ *b as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
 pub fn tempo_parser(arg: &str) -> Result<f64> {
     // Parse tempo argument
     let tempo: f64 = arg[1..].parse()?;
     Ok(tempo)
 }
 
-pub fn pitch_parser(arg: &str) -> Result<i32> {
-    // Fallback integer parse just for the frq generation args
-    let integer = arg.parse::<i32>();
-    match integer {
-        Ok(v) => return Ok(v),
-        Err(_) => (),
+pub fn pitch_parser(arg: &str) -> Result<f64> {
+    // Parse pitch argument as a note name like C4, Db4, e#3 or A4+15 (cents), or a MIDI number
+    if let Some(v) = arg.trim().parse::<f64>().ok().filter(|v| v.is_finite()) {
+        return Ok(v);
     }
-    // Parse pitch argument
-    let note_regex = Regex::new(r"([A-G]#?)(-?\d+)")?;
-    let captures = note_regex.captures(arg).unwrap();
+    let note_regex = Regex::new(r"^([A-Ga-g])([#♯b♭]*)(-?\d+)([+-]\d+(?:\.\d+)?)?$")?;
+    let Some(captures) = note_regex.captures(arg.trim()) else {
+        return Err(anyhow!(
+            "Invalid pitch `{arg}`. Use a note name like C4, Db4 or A4+15, or a MIDI number."
+        ));
+    };
 
-    let note = match captures.get(1).unwrap().as_str() {
+    let note = match captures[1].to_ascii_uppercase().as_str() {
         "C" => 0,
-        "C#" => 1,
         "D" => 2,
-        "D#" => 3,
         "E" => 4,
         "F" => 5,
-        "F#" => 6,
         "G" => 7,
-        "G#" => 8,
         "A" => 9,
-        "A#" => 10,
-        "B" => 11,
-        _ => 0,
+        _ => 11,
     };
-    let octave = captures.get(2).unwrap().as_str().parse::<i32>()? + 1;
-    Ok(octave * 12 + note)
+    // enharmonics fall out of the arithmetic, B#3 is C4 and Cb4 is B3
+    let accidental: i32 = captures[2]
+        .chars()
+        .map(|c| if c == '#' || c == '♯' { 1 } else { -1 })
+        .sum();
+    let octave = captures[3].parse::<i32>()? + 1;
+    let cents = captures
+        .get(4)
+        .map_or(Ok(0.), |c| c.as_str().parse::<f64>())?;
+    Ok((octave * 12 + note + accidental) as f64 + cents / 100.)
 }
 
 #[cfg(test)]
@@ -98,10 +120,66 @@ mod tests {
     #[test]
     fn test_pitch() {
         let pitch = pitch_parser("C4").unwrap();
-        assert_eq!(pitch, 60);
+        assert_eq!(pitch, 60.);
         let pitch = pitch_parser("C5").unwrap();
-        assert_eq!(pitch, 72);
+        assert_eq!(pitch, 72.);
         let pitch = pitch_parser("A4").unwrap();
-        assert_eq!(pitch, 69);
+        assert_eq!(pitch, 69.);
+
+        let table = [
+            ("C-1", 0.),
+            ("c#-1", 1.),
+            ("Db4", 61.),
+            ("Bb3", 58.),
+            ("bb3", 58.),
+            ("A#3", 58.),
+            ("B#3", 60.),
+            ("Cb4", 59.),
+            ("E#4", 65.),
+            ("Fb4", 64.),
+            ("F##4", 67.),
+            ("Gbb4", 65.),
+            ("G♯4", 68.),
+            ("A♭4", 68.),
+            ("A4+15", 69.15),
+            ("A4-15", 68.85),
+            ("a-1-50", 8.5),
+            ("C4+12.5", 60.125),
+            ("G9", 127.),
+            ("60", 60.),
+            ("61.5", 61.5),
+            (" D4 ", 62.),
+        ];
+        for (name, midi) in table {
+            let pitch = pitch_parser(name).unwrap_or_else(|e| panic!("{name}: {e}"));
+            assert!((pitch - midi).abs() < 1e-9, "{name} is {pitch}, not {midi}");
+        }
+
+        // every MIDI key by sharp and flat names
+        let sharps = [
+            "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+        ];
+        let flats = [
+            "C", "Db", "D", "Eb", "E", "F", "Gb", "G", "Ab", "A", "Bb", "B",
+        ];
+        for midi in 0..=127 {
+            let octave = midi as i32 / 12 - 1;
+            for name in [sharps[midi % 12], flats[midi % 12]] {
+                let name = format!("{name}{octave}");
+                assert_eq!(pitch_parser(&name).unwrap(), midi as f64, "{name}");
+                assert_eq!(
+                    pitch_parser(&name.to_lowercase()).unwrap(),
+                    midi as f64,
+                    "{name}"
+                );
+            }
+        }
+
+        for bad in [
+            "", "H4", "C", "C4x", "4C", "C#", "Cb", "C4+", "C4+x", "A4 +15", "#4",
+        ] {
+            let err = pitch_parser(bad).expect_err(bad);
+            assert!(err.to_string().contains("Invalid pitch"), "{bad}: {err}");
+        }
     }
 }
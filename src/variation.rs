@@ -0,0 +1,260 @@
+use crate::consts;
+use crate::util;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use rand_distr::{Distribution, StandardNormal};
+use std::ops::Range;
+
+// How much the sample moves around its slow trend, used to scale the random drift
+pub struct VariationStats {
+    pub f0_std: f64,
+    pub sp_std: Vec<f64>,
+    pub ap_std: Vec<f64>,
+}
+
+fn moving_average(x: &[f64], radius: usize) -> Vec<f64> {
+    // centered moving average, shrinks at the edges
+    (0..x.len())
+        .map(|i| {
+            let lo = i.saturating_sub(radius);
+            let hi = (i + radius + 1).min(x.len());
+            x[lo..hi].iter().sum::<f64>() / (hi - lo) as f64
+        })
+        .collect()
+}
+
+fn residual_std(x: &[f64]) -> f64 {
+    // standard deviation around the slow trend of a curve
+    if x.len() < 2 {
+        return 0.;
+    }
+    let trend = moving_average(x, consts::VARIATION_PERIOD / 2);
+    let var = x
+        .iter()
+        .zip(trend.iter())
+        .map(|(x, t)| (x - t) * (x - t))
+        .sum::<f64>()
+        / x.len() as f64;
+    var.sqrt()
+}
+
+pub fn estimate(
+    f0_off: &[f64],
+    vuv: &[bool],
+    sp: &[Vec<f64>],
+    ap: &[Vec<f64>],
+    range: Range<usize>,
+) -> VariationStats {
+    // Estimate the variation statistics of the sample within a range of frames. Ranges that end
+    // before they start, like a cutoff before the consonant, have no variation
+    let end = range.end.min(f0_off.len());
+    let range = range.start.min(end)..end;
+    let f0_voiced: Vec<f64> = f0_off[range.clone()]
+        .iter()
+        .zip(vuv[range.clone()].iter())
+        .filter(|(_, vuv)| **vuv)
+        .map(|(f0, _)| *f0)
+        .collect();
+    let dims = sp.first().map_or(0, |x| x.len());
+    let sp_std = (0..dims)
+        .map(|k| {
+            let log_sp: Vec<f64> = sp[range.clone()]
+                .iter()
+                .map(|frame| frame[k].max(1e-16).ln())
+                .collect();
+            residual_std(&log_sp)
+        })
+        .collect();
+    let ap_std = (0..dims)
+        .map(|k| {
+            let ap_k: Vec<f64> = ap[range.clone()].iter().map(|frame| frame[k]).collect();
+            residual_std(&ap_k)
+        })
+        .collect();
+
+    VariationStats {
+        f0_std: residual_std(&f0_voiced),
+        sp_std,
+        ap_std,
+    }
+}
+
+pub fn drift(length: usize, rng: &mut StdRng) -> Vec<f64> {
+    // Slow random curve with unit variance at the drift points, smoothly interpolated between them
+    let period = consts::VARIATION_PERIOD;
+    let points: Vec<f64> = (0..length / period + 2)
+        .map(|_| StandardNormal.sample(rng))
+        .collect();
+    (0..length)
+        .map(|i| {
+            let j = i / period;
+            let r = (i % period) as f64 / period as f64;
+            util::lerp(points[j], points[j + 1], util::smoothstep(0., 1., r))
+        })
+        .collect()
+}
+
+fn band_drift(length: usize, dims: usize, rng: &mut StdRng) -> Vec<Vec<f64>> {
+    // Drift for every frequency bin, made from a few independent curves so the envelope
+    // changes shape slowly across frequency too
+    let bands: Vec<Vec<f64>> = (0..consts::VARIATION_BANDS)
+        .map(|_| drift(length, rng))
+        .collect();
+    let last = (consts::VARIATION_BANDS - 1) as f64;
+    (0..length)
+        .map(|i| {
+            (0..dims)
+                .map(|k| {
+                    let pos = last * k as f64 / (dims - 1).max(1) as f64;
+                    let b = (pos.floor() as usize).min(consts::VARIATION_BANDS - 2);
+                    util::lerp(bands[b][i], bands[b + 1][i], pos - b as f64)
+                })
+                .collect()
+        })
+        .collect()
+}
+
+pub fn stretch_weight(t: &[f64], start: usize) -> Vec<f64> {
+    // How frozen every render frame is. Frames that move through the sample at natural speed
+    // already vary, frames that move slower get more drift. Nothing before start is touched.
+    let speed: Vec<f64> = (0..t.len())
+        .map(|i| {
+            if i < start || t.len() < 2 {
+                return 1.;
+            }
+            let i = i.min(t.len() - 2);
+            (t[i + 1] - t[i]).abs()
+        })
+        .collect();
+    moving_average(&speed, consts::VARIATION_PERIOD / 4)
+        .into_iter()
+        .enumerate()
+        .map(|(i, s)| {
+            if i < start {
+                0.
+            } else {
+                (1. - s).clamp(0., 1.)
+            }
+        })
+        .collect()
+}
+
+pub struct Variation {
+    f0: Vec<f64>,
+    sp: Vec<Vec<f64>>,
+    ap: Vec<Vec<f64>>,
+}
+
+impl Variation {
+    pub fn new(stats: &VariationStats, weight: &[f64], amount: f64, seed: u64) -> Self {
+        // Make the drift for every render frame, already scaled by the sample's statistics
+        let mut rng = StdRng::seed_from_u64(seed);
+        let length = weight.len();
+        let dims = stats.sp_std.len();
+        let f0 = drift(length, &mut rng)
+            .into_iter()
+            .zip(weight.iter())
+            .map(|(d, w)| d * w * amount * stats.f0_std)
+            .collect();
+        let scale = |drift: Vec<Vec<f64>>, std: &[f64]| -> Vec<Vec<f64>> {
+            drift
+                .into_iter()
+                .zip(weight.iter())
+                .map(|(frame, w)| {
+                    frame
+                        .into_iter()
+                        .zip(std.iter())
+                        .map(|(d, std)| d * w * amount * std)
+                        .collect()
+                })
+                .collect()
+        };
+        let sp = scale(band_drift(length, dims, &mut rng), &stats.sp_std);
+        let ap = scale(band_drift(length, dims, &mut rng), &stats.ap_std);
+        Self { f0, sp, ap }
+    }
+
+    pub fn apply_f0(&self, f0_off: &mut [f64], vuv: &[bool]) {
+        // pitch drift in semitones, unvoiced frames have no pitch
+        f0_off
+            .iter_mut()
+            .zip(self.f0.iter().zip(vuv.iter()))
+            .filter(|(_, (_, vuv))| **vuv)
+            .for_each(|(f0, (d, _))| *f0 += d);
+    }
+
+    pub fn apply_spectral(&self, sp: &mut [Vec<f64>], ap: &mut [Vec<f64>]) {
+        // spectral envelope drifts in log scale, aperiodicity stays within 0 to 1
+        sp.iter_mut().zip(self.sp.iter()).for_each(|(frame, d)| {
+            frame
+                .iter_mut()
+                .zip(d.iter())
+                .for_each(|(x, d)| *x *= d.exp())
+        });
+        ap.iter_mut().zip(self.ap.iter()).for_each(|(frame, d)| {
+            frame
+                .iter_mut()
+                .zip(d.iter())
+                .for_each(|(x, d)| *x = (*x + d).clamp(0., 1.))
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{drift, estimate, stretch_weight, Variation};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_drift() {
+        // same seed gives the same curve, curve is smooth and roughly unit variance
+        let a = drift(4000, &mut StdRng::seed_from_u64(1));
+        let b = drift(4000, &mut StdRng::seed_from_u64(1));
+        assert_eq!(a, b);
+        assert!(a.windows(2).all(|w| (w[1] - w[0]).abs() < 0.5));
+        let var = a.iter().map(|x| x * x).sum::<f64>() / a.len() as f64;
+        assert!(var > 0.3 && var < 2.);
+    }
+
+    #[test]
+    fn test_variation() {
+        // wobbly pitch and flat spectrum, only the pitch should vary
+        let frames = 200;
+        let f0_off: Vec<f64> = (0..frames).map(|i| 0.2 * (i as f64).sin()).collect();
+        let vuv = vec![true; frames];
+        let sp = vec![vec![1e-4; 16]; frames];
+        let ap = vec![vec![0.1; 16]; frames];
+        let stats = estimate(&f0_off, &vuv, &sp, &ap, 0..frames);
+        assert!(stats.f0_std > 0.1);
+        assert!(stats.sp_std.iter().all(|x| *x < 1e-9));
+
+        // cutoff before the consonant
+        let (consonant, cutoff) = (150, 120);
+        let empty = estimate(&f0_off, &vuv, &sp, &ap, consonant..cutoff);
+        assert_eq!(empty.f0_std, 0.);
+        assert_eq!(empty.sp_std, vec![0.; 16]);
+        assert_eq!(empty.ap_std, vec![0.; 16]);
+        let empty = estimate(&f0_off, &vuv, &sp, &ap, 250..300);
+        assert_eq!(empty.f0_std, 0.);
+
+        // natural speed at first then frozen
+        let t: Vec<f64> = (0..400)
+            .map(|i| if i < 100 { i as f64 } else { 100. })
+            .collect();
+        let weight = stretch_weight(&t, 20);
+        assert_eq!(weight[10], 0.);
+        assert_eq!(weight[50], 0.);
+        assert_eq!(weight[300], 1.);
+
+        let variation = Variation::new(&stats, &weight, 1., 7);
+        let mut f0_render = vec![0.; 400];
+        let mut sp_render = vec![vec![1e-4; 16]; 400];
+        let mut ap_render = vec![vec![0.1; 16]; 400];
+        variation.apply_f0(&mut f0_render, &[true; 400]);
+        variation.apply_spectral(&mut sp_render, &mut ap_render);
+        assert!(f0_render[..50].iter().all(|x| *x == 0.));
+        assert!(f0_render[200..].iter().any(|x| *x != 0.));
+        assert!(sp_render.iter().flatten().all(|x| (x - 1e-4).abs() < 1e-12));
+    }
+}
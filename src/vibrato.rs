@@ -0,0 +1,89 @@
+use crate::consts;
+use crate::util::smoothstep;
+use crate::variation::drift;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+// Generated vibrato, independent of the pitchbend
+pub struct Vibrato {
+    pub rate: f64,    // Hz
+    pub depth: f64,   // cents
+    pub delay: f64,   // seconds after the consonant point
+    pub fade_in: f64, // seconds
+    pub drift: f64,   // 0 to 1
+}
+
+impl Vibrato {
+    pub fn render(&self, t: &[f64], consonant: f64, seed: u64) -> Vec<f64> {
+        // Vibrato in semitones at every frame timestamp in seconds. Drift slowly wobbles the
+        // rate and depth so long notes don't sound mechanical
+        let mut rng = StdRng::seed_from_u64(seed ^ consts::VIBRATO_SEED);
+        let rate_drift = drift(t.len(), &mut rng);
+        let depth_drift = drift(t.len(), &mut rng);
+        let onset = consonant + self.delay;
+
+        let mut phase = 0.;
+        let mut last_t = onset;
+        t.iter()
+            .zip(rate_drift.iter().zip(depth_drift.iter()))
+            .map(|(t, (rate_drift, depth_drift))| {
+                if *t < onset {
+                    return 0.;
+                }
+                let rate = self.rate * (1. + self.drift * consts::VIBRATO_RATE_DRIFT * rate_drift);
+                phase += std::f64::consts::TAU * rate.max(0.) * (t - last_t);
+                last_t = *t;
+                let depth =
+                    self.depth * (1. + self.drift * consts::VIBRATO_DEPTH_DRIFT * depth_drift);
+                let envelope = if self.fade_in > 0. {
+                    smoothstep(onset, onset + self.fade_in, *t)
+                } else {
+                    1.
+                };
+                envelope * depth.max(0.) / 100. * phase.sin()
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Vibrato;
+
+    #[test]
+    fn test_vibrato() {
+        let t: Vec<f64> = (0..600).map(|i| i as f64 * 0.005).collect();
+        let vibrato = Vibrato {
+            rate: 5.,
+            depth: 50.,
+            delay: 0.2,
+            fade_in: 0.3,
+            drift: 0.,
+        };
+        let curve = vibrato.render(&t, 0.1, 0);
+
+        // nothing before the consonant point plus the delay
+        assert!(curve[..60].iter().all(|x| *x == 0.));
+        // faded in to full depth
+        let fade = curve[60..90].iter().fold(0., |m: f64, x| m.max(x.abs()));
+        let full = curve[120..].iter().fold(0., |m: f64, x| m.max(x.abs()));
+        assert!(fade < 0.26, "{fade}");
+        assert!((full - 0.5).abs() < 0.01, "{full}");
+        // 5 Hz over 2.4 seconds is 12 cycles, 24 zero crossings
+        let crossings = curve[120..]
+            .windows(2)
+            .filter(|w| w[0].signum() != w[1].signum())
+            .count();
+        assert!((23..=25).contains(&crossings), "{crossings}");
+
+        // drift changes the curve but stays repeatable
+        let vibrato = Vibrato {
+            drift: 1.,
+            ..vibrato
+        };
+        let a = vibrato.render(&t, 0.1, 7);
+        assert_eq!(a, vibrato.render(&t, 0.1, 7));
+        assert_ne!(a, curve);
+        assert!(a.iter().all(|x| x.abs() < 1.));
+    }
+}
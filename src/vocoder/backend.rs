@@ -0,0 +1,70 @@
+use anyhow::Result;
+
+// Vocoder features of a sample, one frame every FRAME_PERIOD ms
+pub struct Analysis {
+    pub f0: Vec<f64>,      // Hz, 0 for unvoiced frames
+    pub sp: Vec<Vec<f64>>, // power spectral envelope, FFT_SIZE / 2 + 1 bins per frame
+    pub ap: Vec<Vec<f64>>, // aperiodicity from 0 to 1, same size as the envelope
+}
+
+pub trait Analyzer {
+    // Vocoder analysis. Turns audio into F0, spectral envelope and aperiodicity. Coding and
+    // caching the features is left to the feature file
+    fn analyze(&self, audio: &[f64], threshold: Option<f64>) -> Result<Analysis>;
+}
+
+pub trait Synthesizer {
+    // Vocoder synthesis. F0 is in Hz with 0 for unvoiced frames, spectral envelope is power
+    fn synthesize(&self, f0: &[f64], sp: &[Vec<f64>], ap: &[Vec<f64>]) -> Vec<f64>;
+
+    fn synthesize_harmonic(&self, f0: &[f64], sp: &[Vec<f64>], ap: &[Vec<f64>]) -> Vec<f64> {
+        // Synthesize only the periodic part of the features
+        let sp_harmonic: Vec<Vec<f64>> = sp
+            .iter()
+            .zip(ap.iter())
+            .map(|(sp_frame, ap_frame)| {
+                sp_frame
+                    .iter()
+                    .zip(ap_frame.iter())
+                    .map(|(sp_v, ap_v)| sp_v * (1. - ap_v * ap_v))
+                    .collect()
+            })
+            .collect();
+        let ap_harmonic: Vec<Vec<f64>> = ap
+            .iter()
+            .map(|frame| frame.iter().map(|_| 0.).collect())
+            .collect();
+        self.synthesize(f0, &sp_harmonic, &ap_harmonic)
+    }
+
+    fn synthesize_aperiodic(
+        &self,
+        f0: &[f64],
+        sp: &[Vec<f64>],
+        ap: &[Vec<f64>],
+        correct_sp: bool,
+    ) -> Vec<f64> {
+        // Synthesize only the aperiodic part of the features
+        let ap_aperiodic: Vec<Vec<f64>> = ap
+            .iter()
+            .map(|frame| frame.iter().map(|_| 1.).collect())
+            .collect();
+        if correct_sp {
+            let sp_aperiodic: Vec<Vec<f64>> = sp
+                .iter()
+                .zip(ap.iter())
+                .map(|(sp_frame, ap_frame)| {
+                    sp_frame
+                        .iter()
+                        .zip(ap_frame.iter())
+                        .map(|(sp_v, ap_v)| sp_v * ap_v * ap_v)
+                        .collect()
+                })
+                .collect();
+
+            self.synthesize(f0, &sp_aperiodic, &ap_aperiodic)
+        } else {
+            self.synthesize(f0, sp, &ap_aperiodic)
+        }
+    }
+}
@@ -0,0 +1,298 @@
+use crate::consts;
+use crate::util;
+use crate::vocoder::backend::Synthesizer;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use rand_distr::{Distribution, StandardNormal};
+use realfft::RealFftPlanner;
+
+// block size and hop size for the noise overlap-add
+const NOISE_BLOCK: usize = 1024;
+const NOISE_HOP: usize = NOISE_BLOCK / 4;
+
+// Harmonic plus noise backend. Pure Rust, renders from the same features as WORLD
+#[derive(Default)]
+pub struct HarmonicNoise {
+    // noise is seeded on every call like WORLD's, so the same features give the same output
+    pub seed: u64,
+}
+
+impl HarmonicNoise {
+    pub fn new(seed: u64) -> Self {
+        Self { seed }
+    }
+}
+
+impl Synthesizer for HarmonicNoise {
+    fn synthesize(&self, f0: &[f64], sp: &[Vec<f64>], ap: &[Vec<f64>]) -> Vec<f64> {
+        // same output length as WORLD's synthesis, nothing for no frames
+        if f0.is_empty() {
+            return Vec::new();
+        }
+        let out_length =
+            f0.len() * consts::FRAME_PERIOD as usize * consts::SAMPLE_RATE as usize / 1000;
+        let mut harmonic = synthesize_sinusoids(f0, sp, ap, out_length);
+        let noise = synthesize_noise(f0, sp, ap, out_length, self.seed);
+        harmonic
+            .iter_mut()
+            .zip(noise.iter())
+            .for_each(|(h, n)| *h += n);
+        harmonic
+    }
+}
+
+fn sample_envelope(frame: &[f64], freq: f64) -> f64 {
+    // linearly sample a spectral frame at a frequency in Hz
+    let fft_size = 2 * (frame.len() - 1);
+    let bin =
+        (freq * fft_size as f64 / consts::SAMPLE_RATE as f64).clamp(0., (frame.len() - 1) as f64);
+    let index = bin.floor() as usize;
+    if index >= frame.len() - 1 {
+        return frame[frame.len() - 1];
+    }
+    util::lerp(frame[index], frame[index + 1], bin.fract())
+}
+
+fn synthesize_sinusoids(
+    f0: &[f64],
+    sp: &[Vec<f64>],
+    ap: &[Vec<f64>],
+    out_length: usize,
+) -> Vec<f64> {
+    // Additive synthesis of the periodic part with one running phase shared by all harmonics
+    let fs = consts::SAMPLE_RATE as f64;
+    let nyquist = fs / 2.;
+    let hop = fs * consts::FRAME_PERIOD / 1000.;
+    let n = f0.len();
+
+    // harmonic amplitudes per frame. a pulse train with period T0 and response sqrt(sp) has
+    // harmonics of amplitude 2 * sqrt(sp / T0), matching WORLD's periodic response level
+    let amplitudes: Vec<Vec<f64>> = f0
+        .iter()
+        .zip(sp.iter().zip(ap.iter()))
+        .map(|(f0, (sp_frame, ap_frame))| {
+            if *f0 <= 0. {
+                return Vec::new();
+            }
+            let harmonics = (nyquist / f0).floor() as usize;
+            (1..=harmonics)
+                .map(|k| {
+                    let freq = k as f64 * f0;
+                    let sp_v = sample_envelope(sp_frame, freq).max(0.);
+                    let ap_v = sample_envelope(ap_frame, freq).clamp(0., 1.);
+                    2. * (sp_v * (1. - ap_v * ap_v) * f0 / fs).sqrt()
+                })
+                .collect()
+        })
+        .collect();
+
+    let mut signal = vec![0.; out_length];
+    let mut phase: f64 = 0.;
+    for (i, x) in signal.iter_mut().enumerate() {
+        let pos = i as f64 / hop;
+        let j = (pos.floor() as usize).min(n - 1);
+        let j1 = (j + 1).min(n - 1);
+        let r = pos - j as f64;
+
+        // glide between voiced frames, hold the voiced side at voicing boundaries
+        let curr_f0 = match (f0[j] > 0., f0[j1] > 0.) {
+            (true, true) => util::lerp(f0[j], f0[j1], r),
+            (true, false) => f0[j],
+            (false, true) => f0[j1],
+            (false, false) => continue,
+        };
+        phase = (phase + std::f64::consts::TAU * curr_f0 / fs) % std::f64::consts::TAU;
+
+        // sin(k * phase) by recurrence, keeps the harmonics phase locked to the fundamental
+        let harmonics = (nyquist / curr_f0).floor() as usize;
+        let two_cos = 2. * phase.cos();
+        let mut s_prev = 0.;
+        let mut s_curr = phase.sin();
+        let mut y = 0.;
+        for k in 0..harmonics {
+            let a0 = amplitudes[j].get(k).copied().unwrap_or(0.);
+            let a1 = amplitudes[j1].get(k).copied().unwrap_or(0.);
+            y += util::lerp(a0, a1, r) * s_curr;
+            let s_next = two_cos * s_curr - s_prev;
+            s_prev = s_curr;
+            s_curr = s_next;
+        }
+        *x = y;
+    }
+    signal
+}
+
+fn synthesize_noise(
+    f0: &[f64],
+    sp: &[Vec<f64>],
+    ap: &[Vec<f64>],
+    out_length: usize,
+    seed: u64,
+) -> Vec<f64> {
+    // Aperiodic part as spectrally shaped white noise, overlap-added with a Hann window
+    let fs = consts::SAMPLE_RATE as f64;
+    let hop = fs * consts::FRAME_PERIOD / 1000.;
+    let n = f0.len();
+    let bins = NOISE_BLOCK / 2 + 1;
+
+    let mut planner = RealFftPlanner::<f64>::new();
+    let forward = planner.plan_fft_forward(NOISE_BLOCK);
+    let inverse = planner.plan_fft_inverse(NOISE_BLOCK);
+    let mut block = forward.make_input_vec();
+    let mut spectrum = forward.make_output_vec();
+
+    // hann window squared sums to 1.5 at 75% overlap, scale so white noise keeps unit variance
+    let window: Vec<f64> = (0..NOISE_BLOCK)
+        .map(|i| {
+            let w = 0.5 - 0.5 * (std::f64::consts::TAU * i as f64 / NOISE_BLOCK as f64).cos();
+            w / 1.5f64.sqrt()
+        })
+        .collect();
+
+    let mut rng = StdRng::seed_from_u64(seed ^ consts::NOISE_SEED);
+    // signal is offset by a whole block so the first samples get full overlap too
+    let mut signal = vec![0.; out_length + 2 * NOISE_BLOCK];
+    let blocks = (out_length + NOISE_BLOCK) / NOISE_HOP + 1;
+    for b in 0..blocks {
+        // frame at the center of the block
+        let start = b * NOISE_HOP;
+        let pos = ((start as f64 - NOISE_BLOCK as f64 / 2.) / hop).max(0.);
+        let j = (pos.floor() as usize).min(n - 1);
+        let j1 = (j + 1).min(n - 1);
+        let r = (pos - j as f64).clamp(0., 1.);
+
+        block
+            .iter_mut()
+            .for_each(|x| *x = StandardNormal.sample(&mut rng));
+        forward
+            .process(&mut block, &mut spectrum)
+            .expect("Buffers are made by the FFT plan.");
+
+        // unvoiced frames are all noise, voiced frames use the aperiodicity as a ratio
+        for (k, c) in spectrum.iter_mut().enumerate() {
+            let freq = k as f64 * fs / NOISE_BLOCK as f64;
+            let psd_at = |j: usize| {
+                let sp_v = sample_envelope(&sp[j], freq).max(0.);
+                if f0[j] > 0. {
+                    let ap_v = sample_envelope(&ap[j], freq).clamp(0., 1.);
+                    sp_v * ap_v * ap_v
+                } else {
+                    sp_v
+                }
+            };
+            let magnitude = util::lerp(psd_at(j), psd_at(j1), r).sqrt();
+            *c *= magnitude / NOISE_BLOCK as f64;
+        }
+        spectrum[0].im = 0.;
+        spectrum[bins - 1].im = 0.;
+        inverse
+            .process(&mut spectrum, &mut block)
+            .expect("Buffers are made by the FFT plan.");
+
+        signal[start..start + NOISE_BLOCK]
+            .iter_mut()
+            .zip(block.iter().zip(window.iter()))
+            .for_each(|(s, (x, w))| *s += x * w);
+    }
+
+    // drop the lead-in so block centers line up with frame times
+    signal.drain(..NOISE_BLOCK);
+    signal.truncate(out_length);
+    signal
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HarmonicNoise;
+    use crate::consts;
+    use crate::vocoder::backend::Synthesizer;
+    use crate::world::vocoder::World;
+
+    fn test_features(
+        f0: f64,
+        frames: usize,
+        ap_v: f64,
+    ) -> (Vec<f64>, Vec<Vec<f64>>, Vec<Vec<f64>>) {
+        // flat features with a simple formant-ish envelope
+        let dims = (consts::FFT_SIZE / 2 + 1) as usize;
+        let sp_frame: Vec<f64> = (0..dims)
+            .map(|i| {
+                let freq = i as f64 * consts::SAMPLE_RATE as f64 / consts::FFT_SIZE as f64;
+                1e-4 / (1. + ((freq - 800.) / 400.).powi(2)) + 1e-7
+            })
+            .collect();
+        (
+            vec![f0; frames],
+            vec![sp_frame; frames],
+            vec![vec![ap_v; dims]; frames],
+        )
+    }
+
+    fn rms(x: &[f64]) -> f64 {
+        (x.iter().map(|x| x * x).sum::<f64>() / x.len() as f64).sqrt()
+    }
+
+    fn db(a: f64, b: f64) -> f64 {
+        20. * (a / b).log10()
+    }
+
+    #[test]
+    fn test_hnm_against_world() {
+        // levels of both parts should match WORLD's within a dB or so
+        for f0 in [110., 441., 880.] {
+            let (f0, sp, ap) = test_features(f0, 100, 0.3);
+            let world_hm = World.synthesize_harmonic(&f0, &sp, &ap);
+            let hnm_hm = HarmonicNoise::default().synthesize_harmonic(&f0, &sp, &ap);
+            let world_ap = World.synthesize_aperiodic(&f0, &sp, &ap, true);
+            let hnm_ap = HarmonicNoise::default().synthesize_aperiodic(&f0, &sp, &ap, true);
+            assert_eq!(world_hm.len(), hnm_hm.len());
+            assert_eq!(world_ap.len(), hnm_ap.len());
+
+            let mid = world_hm.len() / 4..3 * world_hm.len() / 4;
+            let hm_diff = db(rms(&hnm_hm[mid.clone()]), rms(&world_hm[mid.clone()]));
+            let ap_diff = db(rms(&hnm_ap[mid.clone()]), rms(&world_ap[mid]));
+            assert!(hm_diff.abs() < 0.5, "harmonic level off by {hm_diff} dB");
+            assert!(ap_diff.abs() < 1.5, "aperiodic level off by {ap_diff} dB");
+        }
+    }
+
+    #[test]
+    fn test_hnm_periodicity() {
+        // 441 Hz is exactly 100 samples per period, harmonic output should repeat
+        let (f0, sp, ap) = test_features(441., 100, 0.);
+        let syn = HarmonicNoise::default().synthesize(&f0, &sp, &ap);
+        let mid = &syn[syn.len() / 4..3 * syn.len() / 4];
+        let corr: f64 = mid.windows(101).map(|w| w[0] * w[100]).sum();
+        let energy: f64 = mid.iter().map(|x| x * x).sum();
+        assert!(corr / energy > 0.99);
+
+        // unvoiced frames are rendered as noise like in WORLD
+        let (f0, sp, ap) = test_features(0., 100, 0.);
+        let syn = HarmonicNoise::default().synthesize(&f0, &sp, &ap);
+        let mid = &syn[syn.len() / 4..3 * syn.len() / 4];
+        let corr: f64 = mid.windows(101).map(|w| w[0] * w[100]).sum();
+        let energy: f64 = mid.iter().map(|x| x * x).sum();
+        assert!(energy > 0.);
+        assert!(corr / energy < 0.5);
+    }
+
+    #[test]
+    fn test_hnm_seed() {
+        // same seed gives the same noise, another seed a different take
+        let (f0, sp, ap) = test_features(220., 50, 0.5);
+        let a = HarmonicNoise::new(1).synthesize(&f0, &sp, &ap);
+        let b = HarmonicNoise::new(1).synthesize(&f0, &sp, &ap);
+        let c = HarmonicNoise::new(2).synthesize(&f0, &sp, &ap);
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+    }
+
+    #[test]
+    fn test_hnm_empty() {
+        // no frames render nothing instead of panicking, like WORLD
+        let synth = HarmonicNoise::default();
+        assert!(synth.synthesize(&[], &[], &[]).is_empty());
+        assert!(synth.synthesize_harmonic(&[], &[], &[]).is_empty());
+        assert!(synth.synthesize_aperiodic(&[], &[], &[], true).is_empty());
+    }
+}
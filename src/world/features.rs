@@ -5,8 +5,12 @@ use std::{
 };
 
 use crate::consts;
+use crate::vocoder::backend::{Analysis, Analyzer};
 use anyhow::Result;
-use rsworld::{cheaptrick, code_aperiodicity, code_spectral_envelope, d4c, harvest};
+use rsworld::{
+    cheaptrick, code_aperiodicity, code_spectral_envelope, d4c, decode_aperiodicity,
+    decode_spectral_envelope, harvest,
+};
 use rsworld_sys::{CheapTrickOption, D4COption, HarvestOption};
 use serde::{Deserialize, Serialize};
 
@@ -18,6 +22,8 @@ pub struct WorldFeatures {
     pub f0: Vec<f64>,
     pub mgc: Vec<Vec<f64>>,
     pub bap: Vec<Vec<f64>>,
+    // Per frame instability for finding stretchable regions
+    pub instability: Vec<f64>,
 }
 
 pub fn to_feature_path<P: AsRef<Path>>(path: P) -> PathBuf {
@@ -29,7 +35,7 @@ pub fn to_feature_path<P: AsRef<Path>>(path: P) -> PathBuf {
         .with_extension(consts::FEATURE_EXT)
 }
 
-fn calculate_base_f0(f0: &Vec<f64>) -> f64 {
+fn calculate_base_f0(f0: &[f64]) -> f64 {
     // Get base F0. Averages the whole F0 curve with strong bias on flat areas.
     let n = f0.len();
     let mut base_f0 = 0.;
@@ -57,12 +63,64 @@ fn calculate_base_f0(f0: &Vec<f64>) -> f64 {
     base_f0
 }
 
-pub fn generate_features<P: AsRef<Path>>(
-    path: P,
-    audio: Vec<f64>,
-    threshold: Option<f64>,
-) -> Result<WorldFeatures> {
-    // Generate all required WORLD features
+fn calculate_instability(f0: &[f64], mgc: &[Vec<f64>]) -> Vec<f64> {
+    // How much each frame changes. Spectral flux from the MGC plus the local pitch deviation.
+    let n = f0.len();
+    if n < 2 {
+        return vec![0.; n];
+    }
+
+    // distance between neighboring MGC frames, ignoring the energy coefficient
+    let flux: Vec<f64> = (0..n)
+        .map(|i| {
+            let j = if i == 0 { 1 } else { i - 1 };
+            mgc[i]
+                .iter()
+                .zip(mgc[j].iter())
+                .skip(1)
+                .map(|(a, b)| (a - b) * (a - b))
+                .sum::<f64>()
+                .sqrt()
+        })
+        .collect();
+    let mut sorted_flux = flux.clone();
+    sorted_flux.sort_by(|a, b| a.total_cmp(b));
+    let median_flux = sorted_flux[n / 2].max(1e-9);
+
+    // standard deviation of pitch in semitones around each frame. unvoiced frames are unstable
+    let semitones: Vec<Option<f64>> = f0
+        .iter()
+        .map(|f0| {
+            if *f0 > 0. {
+                Some(12. * f0.log2())
+            } else {
+                None
+            }
+        })
+        .collect();
+    let pitch_dev: Vec<f64> = (0..n)
+        .map(|i| {
+            if semitones[i].is_none() {
+                return consts::UNVOICED_INSTABILITY;
+            }
+            let window = &semitones[i.saturating_sub(consts::INSTABILITY_WINDOW)
+                ..(i + consts::INSTABILITY_WINDOW + 1).min(n)];
+            let voiced: Vec<f64> = window.iter().flatten().copied().collect();
+            let mean = voiced.iter().sum::<f64>() / voiced.len() as f64;
+            (voiced.iter().map(|x| (x - mean) * (x - mean)).sum::<f64>() / voiced.len() as f64)
+                .sqrt()
+        })
+        .collect();
+
+    flux.iter()
+        .zip(pitch_dev.iter())
+        .map(|(flux, dev)| flux / median_flux + dev)
+        .collect()
+}
+
+pub fn analyze(audio: &[f64], threshold: Option<f64>) -> Analysis {
+    // Analyze audio with WORLD
+    let audio = audio.to_vec();
     let harvest_opts = HarvestOption {
         f0_floor: consts::F0_FLOOR,
         f0_ceil: consts::F0_CEIL,
@@ -100,9 +158,14 @@ pub fn generate_features<P: AsRef<Path>>(
         })
     });
 
+    Analysis { f0, sp, ap }
+}
+
+pub fn encode_features(analysis: Analysis) -> WorldFeatures {
+    // Code features from any analyzer to reduce feature file size
+    let Analysis { f0, sp, ap } = analysis;
     let base_f0 = calculate_base_f0(&f0);
 
-    // Code features to reduce feature file size
     let mgc = code_spectral_envelope(
         &sp,
         f0.len() as i32,
@@ -111,13 +174,38 @@ pub fn generate_features<P: AsRef<Path>>(
         consts::MGC_DIMS,
     );
     let bap = code_aperiodicity(&ap, f0.len() as i32, consts::SAMPLE_RATE as i32);
+    let instability = calculate_instability(&f0, &mgc);
 
-    let features = WorldFeatures {
+    WorldFeatures {
         base_f0,
         f0,
         mgc,
         bap,
-    };
+        instability,
+    }
+}
+
+pub fn decode_features(features: &WorldFeatures) -> (Vec<Vec<f64>>, Vec<Vec<f64>>) {
+    // Decode coded WORLD features to spectral envelope and aperiodicity
+    let feature_length = features.f0.len() as i32;
+    let sp = decode_spectral_envelope(
+        &features.mgc,
+        feature_length,
+        consts::SAMPLE_RATE as i32,
+        consts::FFT_SIZE,
+    );
+    let ap = decode_aperiodicity(&features.bap, feature_length, consts::SAMPLE_RATE as i32);
+    (sp, ap)
+}
+
+pub fn generate_features<A: Analyzer, P: AsRef<Path>>(
+    analyzer: &A,
+    path: P,
+    audio: Vec<f64>,
+    threshold: Option<f64>,
+) -> Result<WorldFeatures> {
+    // Analyze audio with the given backend and cache the features
+    let features = encode_features(analyzer.analyze(&audio, threshold)?);
 
     let feature_path = to_feature_path(path);
     let bin = bincode::serialize(&features)?;
@@ -141,9 +229,11 @@ pub fn read_features<P: AsRef<Path>>(path: P) -> Result<WorldFeatures> {
 mod tests {
     use rsworld::{decode_aperiodicity, decode_spectral_envelope, synthesis};
 
-    use super::{generate_features, read_features};
+    use super::{decode_features, encode_features, generate_features, read_features};
     use crate::audio::read_write::{read_audio, write_audio};
     use crate::consts;
+    use crate::vocoder::backend::Analysis;
+    use crate::world::vocoder::World;
     use std::fs::File;
     use std::io::Write;
     use std::path::Path;
@@ -158,8 +248,7 @@ mod tests {
         println!("gt: {}", audio.len());
 
         let now = Instant::now();
-        let features =
-            generate_features(&path, audio, None).expect("Cannot generate WORLD features");
+        generate_features(&World, path, audio, None).expect("Cannot generate WORLD features");
         println!("Feature Generation: {:.2?}", now.elapsed());
         let now = Instant::now();
         let features = read_features(&feature_path).expect("Cannot read WORLD features");
@@ -189,6 +278,29 @@ mod tests {
         write_audio(synth_path, &syn).expect("Cannot write");
     }
 
+    #[test]
+    fn test_encode_features() {
+        // features from any analyzer survive coding for the feature file
+        let dims = (consts::FFT_SIZE / 2 + 1) as usize;
+        let analysis = Analysis {
+            f0: vec![220.; 100],
+            sp: vec![vec![1e-4; dims]; 100],
+            ap: vec![vec![0.5; dims]; 100],
+        };
+        let features = encode_features(analysis);
+        assert_eq!(features.f0, vec![220.; 100]);
+        assert!((features.base_f0 - 220.).abs() < 1e-9);
+        assert_eq!(features.instability.len(), 100);
+
+        let (sp, ap) = decode_features(&features);
+        assert_eq!((sp.len(), sp[0].len()), (100, dims));
+        assert_eq!((ap.len(), ap[0].len()), (100, dims));
+        let sp_mid = sp[50][dims / 2];
+        let ap_mid = ap[50][dims / 2];
+        assert!((sp_mid / 1e-4 - 1.).abs() < 1e-3, "{sp_mid}");
+        assert!((ap_mid - 0.5).abs() < 1e-3, "{ap_mid}");
+    }
+
     #[test]
     fn test_decode_features() {
         let test_path =
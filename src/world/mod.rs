@@ -1,2 +1,3 @@
 pub mod features;
 pub mod synthesis;
+pub mod vocoder;
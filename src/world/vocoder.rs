@@ -0,0 +1,18 @@
+use super::{features, synthesis};
+use crate::vocoder::backend::{Analysis, Analyzer, Synthesizer};
+use anyhow::Result;
+
+// WORLD vocoder backend
+pub struct World;
+
+impl Analyzer for World {
+    fn analyze(&self, audio: &[f64], threshold: Option<f64>) -> Result<Analysis> {
+        Ok(features::analyze(audio, threshold))
+    }
+}
+
+impl Synthesizer for World {
+    fn synthesize(&self, f0: &[f64], sp: &[Vec<f64>], ap: &[Vec<f64>]) -> Vec<f64> {
+        synthesis::synthesize(f0, sp, ap)
+    }
+}